@@ -0,0 +1,40 @@
+//Times the encoder on the 1024x1024 gradient that `qoi demo` encodes (see encode_debug in main.rs).
+//Compare `cargo bench --bench encode` against `cargo bench --features simd --bench encode`.
+use colors_transform::{Color, Hsl};
+use qoi::qoi_lib::*;
+use std::time::{Duration, Instant};
+
+const SIZE: u32 = 1024;
+const ROUNDS: u32 = 10;
+
+fn test_image() -> Image {
+    let mut pixels: Vec<Pixel> = Vec::with_capacity((SIZE * SIZE) as usize);
+    for i in 0..SIZE {
+        let alpha: f64 = -(255.0 / 1024.0) * (i as f64) + 255.0;
+        for j in 0..SIZE {
+            let rgb = Hsl::from(0.3515625 * j as f32, 100.0, 50.0).to_rgb();
+            pixels.push(Pixel::new(
+                rgb.get_red() as u8,
+                rgb.get_green() as u8,
+                rgb.get_blue() as u8,
+                alpha as u8,
+            ));
+        }
+    }
+    Image::from_pixels(pixels, SIZE, SIZE, 4, 0).unwrap()
+}
+
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start: Instant = Instant::now();
+    for _ in 0..ROUNDS {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let img: Image = test_image();
+    let simd: &str = if cfg!(feature = "simd") { "simd" } else { "scalar" };
+    println!("{}x{} image, {} diffs, mean of {} rounds", SIZE, SIZE, simd, ROUNDS);
+    println!("encode: {:?} ({} bytes)", time(|| encode_from_image(&img)), encode_from_image(&img).len());
+}
@@ -0,0 +1,44 @@
+//Compares the serial encoder/decoder with the rayon-based tiled variants.
+//Run with `cargo bench --features rayon --bench parallel`.
+use qoi::qoi_lib::*;
+use std::time::{Duration, Instant};
+
+const SIZE: u32 = 2048;
+const TILES: usize = 16;
+const ROUNDS: u32 = 5;
+
+fn test_image() -> Image {
+    let mut pixels: Vec<Pixel> = Vec::with_capacity((SIZE * SIZE) as usize);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let noise: u32 = (x * 7 + y * 13) % 5;
+            pixels.push(Pixel::new(
+                (x / 8) as u8,
+                (y / 8) as u8,
+                (((x ^ y) % 256) as u8).wrapping_add(noise as u8),
+                255,
+            ));
+        }
+    }
+    Image::from_pixels(pixels, SIZE, SIZE, 4, 0).unwrap()
+}
+
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start: Instant = Instant::now();
+    for _ in 0..ROUNDS {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / ROUNDS
+}
+
+fn main() {
+    let img: Image = test_image();
+    let serial: Vec<u8> = encode_from_image(&img);
+    let parallel: Vec<u8> = encode_parallel(&img, TILES);
+
+    println!("{}x{} image, {} tiles, mean of {} rounds", SIZE, SIZE, TILES, ROUNDS);
+    println!("encode serial:   {:?} ({} bytes)", time(|| encode_from_image(&img)), serial.len());
+    println!("encode parallel: {:?} ({} bytes)", time(|| encode_parallel(&img, TILES)), parallel.len());
+    println!("decode serial:   {:?}", time(|| decode(serial.clone()).unwrap()));
+    println!("decode parallel: {:?}", time(|| decode_parallel(&parallel).unwrap()));
+}
@@ -0,0 +1,71 @@
+//Compares keyframe-only and delta-encoded sequences on a synthetic animation where only a corner changes each frame.
+//Run with `cargo bench --bench sequence`.
+use qoi::qoi_lib::*;
+use std::time::{Duration, Instant};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FRAMES: u32 = 60;
+const CORNER: u32 = 64;
+const KEYFRAME_INTERVAL: usize = 30;
+
+fn background(x: u32, y: u32) -> Pixel {
+    Pixel::new(
+        (x * 255 / WIDTH) as u8,
+        (y * 255 / HEIGHT) as u8,
+        ((x ^ y) & 0xff) as u8,
+        255,
+    )
+}
+
+fn frames() -> Vec<Image> {
+    (0..FRAMES)
+        .map(|frame| {
+            let pixels: Vec<Pixel> = (0..HEIGHT)
+                .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+                .map(|(x, y)| match x < CORNER && y < CORNER {
+                    true => Pixel::new(
+                        (x * 4 + frame * 7) as u8,
+                        (y * 4) as u8,
+                        (frame * 13) as u8,
+                        255,
+                    ),
+                    false => background(x, y),
+                })
+                .collect();
+            Image::from_pixels(pixels, WIDTH, HEIGHT, 4, 0).unwrap()
+        })
+        .collect()
+}
+
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start: Instant = Instant::now();
+    std::hint::black_box(f());
+    start.elapsed()
+}
+
+fn main() {
+    let frames: Vec<Image> = frames();
+    println!(
+        "{} frames of {}x{}, {}x{} corner changing",
+        FRAMES, WIDTH, HEIGHT, CORNER, CORNER
+    );
+
+    let keyframes: Vec<u8> = encode_sequence(&frames, 30);
+    let deltas: Vec<u8> = encode_sequence_with_keyframes(&frames, 30, KEYFRAME_INTERVAL);
+    assert!(decode_sequence(&deltas).unwrap().0 == frames);
+
+    println!(
+        "keyframes only:          encode {:?}, decode {:?} ({} bytes)",
+        time(|| encode_sequence(&frames, 30)),
+        time(|| decode_sequence(&keyframes)),
+        keyframes.len()
+    );
+    println!(
+        "keyframe every {} frames: encode {:?}, decode {:?} ({} bytes)",
+        KEYFRAME_INTERVAL,
+        time(|| encode_sequence_with_keyframes(&frames, 30, KEYFRAME_INTERVAL)),
+        time(|| decode_sequence(&deltas)),
+        deltas.len()
+    );
+}
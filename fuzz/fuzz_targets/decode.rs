@@ -0,0 +1,15 @@
+//Run with `cargo +nightly fuzz run decode` from the repository root.
+//Every decoding entry point must return an error on malformed input instead of panicking.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use qoi::qoi_lib::*;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_header(data);
+    let _ = verify(data);
+    let _ = decode_slice(data);
+    let _ = decode_from_reader(&mut std::io::Cursor::new(data));
+    let _ = decode_into(data, &mut [0; 4096]);
+    let _ = decode_bmp(data);
+});
@@ -1,17 +1,50 @@
 //! # qoi_img
 //! `qoi_img` is a bad, from-scratch implementation of the decoder and encoder for the `.qoi` file format as described as on [qoiformat.org](https://qoiformat.org/qoi-specification.pdf).
 //! This crate should not be published as better crates are available, e.g. [rapid-qoi](https://crates.io/crates/rapid-qoi).
+//!
+//! The codec itself only needs `alloc`: without the default `std` feature the crate is `#![no_std]` and leaves out
+//! everything touching files, readers and writers and the floating point colour transforms. PNG output additionally
+//! needs the `png` feature.
+//! Logging goes through the `log` crate and is compiled out without the default `log` feature.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code, unused_variables)]
+extern crate alloc;
+
 pub mod qoi_lib {
 
-    use log::{debug, info, Level, LevelFilter, Record, SetLoggerError};
-    use std::fmt;
+    use alloc::collections::BTreeSet;
+    use alloc::format;
+    use alloc::string::String;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::fmt;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    #[cfg(feature = "log")]
+    use log::{debug, info};
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "png")]
+    use std::ffi::OsStr;
+    #[cfg(feature = "std")]
+    use std::ffi::OsString;
+    #[cfg(feature = "std")]
     use std::fs::*;
+    #[cfg(feature = "std")]
     use std::io::prelude::*;
+    #[cfg(feature = "std")]
     use std::io::BufWriter;
-    use std::path::Path;
-    use png;
-    
+    #[cfg(feature = "std")]
+    use std::path::{Path, PathBuf};
+
+    //without the `log` feature the log macros expand to nothing
+    #[cfg(not(feature = "log"))]
+    macro_rules! debug {
+        ($($arg:tt)*) => {};
+    }
+    #[cfg(not(feature = "log"))]
+    macro_rules! info {
+        ($($arg:tt)*) => {};
+    }
 
     use array_init;
 
@@ -19,82 +52,63 @@ pub mod qoi_lib {
     #[derive(Debug, Clone, PartialEq)]
     pub enum ImgError {
         DataError,
+        ByteCountError {
+            expected_multiple: usize,
+            got: usize,
+        },
         PixelNumberError,
         DecodeError,
+        /// The stream decodes to a different number of pixels than its header's width × height.
+        PixelCountMismatch {
+            expected: usize,
+            actual: usize,
+        },
         HeaderError,
+        /// `channels` is not 3 or 4, or `colorspace` is not 0 or 1.
+        InvalidFormat {
+            channels: u8,
+            colorspace: u8,
+        },
+        #[cfg(feature = "std")]
+        IoError(std::io::ErrorKind),
     }
     //inherit from base Error
-    impl std::error::Error for ImgError {}
+    impl core::error::Error for ImgError {}
 
     //Output for error handling
     impl fmt::Display for ImgError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
-                ImgError::DataError => {
-                    write!(f, "invalid number of bytes (must be devisible by 4)")
-                }
+                ImgError::DataError => write!(f, "invalid input data"),
+                ImgError::ByteCountError {
+                    expected_multiple,
+                    got,
+                } => write!(
+                    f,
+                    "invalid number of bytes: {got} is not divisible by {expected_multiple}"
+                ),
                 ImgError::PixelNumberError => {
                     write!(f, "number of pixels does not match height and width params")
                 }
                 ImgError::DecodeError => write!(f, "decoder failed to construct valid image"),
+                ImgError::PixelCountMismatch { expected, actual } => write!(
+                    f,
+                    "header declares {expected} pixels but the stream contains {actual}"
+                ),
                 ImgError::HeaderError => write!(f, "not a valid QOI file header"),
+                ImgError::InvalidFormat {
+                    channels,
+                    colorspace,
+                } => write!(
+                    f,
+                    "invalid format: {channels} channels and colorspace {colorspace}, expected 3 or 4 channels and colorspace 0 or 1"
+                ),
+                #[cfg(feature = "std")]
+                ImgError::IoError(kind) => write!(f, "I/O error: {kind}"),
             }
         }
     }
 
-    //boilerplate implementation of the log crate
-    pub struct SimpleLogger;
-
-    impl log::Log for SimpleLogger {
-        fn enabled(&self, metadata: &log::Metadata) -> bool {
-            metadata.level() <= Level::Debug
-        }
-        fn log(&self, record: &Record) {
-            if self.enabled(record.metadata()) {
-                eprintln!("{} - {}", record.level(), record.args());
-            }
-        }
-        fn flush(&self) {}
-    }
-    //logging boilerplate
-    static LOGGER: SimpleLogger = SimpleLogger;
-    /// Initialises the logger provided by [log](https://crates.io/crates/log)
-    /// # Example
-    ///
-    /// ```
-    /// # use std::error::Error;
-    /// # use crate::qoi::qoi_lib::*;
-    /// # use log::*;
-    /// # fn main() -> Result<(), Box<ImgError>> {
-    /// let level = LevelFilter::Debug;
-    /// init(level).expect("Failed to initialize logger");
-    /// #
-    /// # Ok(())
-    /// #
-    /// # }
-    /// ```
-    ///
-    /// If you want to pass the error on replace the `println!`:
-    ///
-    /// ```
-    /// # use std::error::Error;
-    /// # use crate::qoi::qoi_lib::*;
-    /// # use log::*;
-    /// # fn main() -> Result<(), ImgError> {
-    /// let level = LevelFilter::Debug;
-    /// match init(level) {
-    /// Ok(()) => (),
-    /// Err(e) => println!("Logger failed to initialize!")
-    /// }
-    /// #
-    /// # Ok(())
-    /// #
-    /// # }
-    /// ```
-    pub fn init(level: LevelFilter) -> Result<(), SetLoggerError> {
-        log::set_logger(&LOGGER).map(|()| log::set_max_level(level))
-    }
-
     /// Custom image struct, which is used to store decoded data. Used by [encode_from_image] to encode the necessary data in bytes. Requires a Vector over [Pixel] values, `Vec<Pixel>`,
     /// which can be generated by [`self::new`] if given byte data. Otherwise, [self.pixels] must be given filled vector.
     /// `height` and `width` are given as u32 (note that qoi encoding does not guarantee functionality for images containing over 4000000 pixels.)
@@ -112,13 +126,14 @@ pub mod qoi_lib {
     /// let width: u32 = 1024;
     /// let channels: u8 = 4;
     /// let colorspace: u8 = 0;
-    /// let img: Image = Image::new(pixels, height, width, channels, colorspace)?;
+    /// let img: Image = Image::new(pixels, width, height, channels, colorspace)?;
     /// #
     /// # Ok(())
     /// # }
     /// ```
     ///
     /// Alternatively, [`Image::from_pixels()`] can be used to create an image from pixel values.
+    #[derive(Clone, PartialEq)]
     pub struct Image {
         pixels: Vec<Pixel>,
         height: u32,
@@ -128,59 +143,148 @@ pub mod qoi_lib {
     }
 
     impl Image {
-        //Image constructor, expects an array of u8 pixels values in order, left to right, top to bottom.
+        /// Image constructor, expects an array of u8 pixels values in order, left to right, top to bottom.
+        /// Prefer [Image::builder()], whose named setters cannot mix up `height` and `width`.
         pub fn new(
             data: Vec<u8>,
-            height: u32,
             width: u32,
+            height: u32,
             channels: u8,
             colorspace: u8,
         ) -> Result<Image, ImgError> {
-            let alpha: bool;
-            if channels == 4 {
-                alpha = true;
-            } else {
-                alpha = false;
-            }
-            let pixels: Vec<Pixel> = match Image::pixels_from_bytes(data, alpha) {
-                Ok(out) => out,
-                Err(error) => return Err(error),
-            };
-            if pixels.len() == (height * width) as usize {
-                let out: Image = Image {
-                    pixels,
-                    height,
-                    width,
-                    channels,
-                    colorspace,
-                };
-                Ok(out)
-            } else {
-                Err(ImgError::PixelNumberError)
-            }
+            check_format(channels, colorspace)?;
+            let alpha: bool = channels == 4;
+            let pixels: Vec<Pixel> = Image::pixels_from_bytes(data, alpha)?;
+            Image::from_pixels(pixels, width, height, channels, colorspace)
         }
 
         pub fn from_pixels(
             pixels: Vec<Pixel>,
-            height: u32,
             width: u32,
+            height: u32,
             channels: u8,
             colorspace: u8,
-        ) -> Image {
-            let img = Image {
+        ) -> Result<Image, ImgError> {
+            check_format(channels, colorspace)?;
+            if pixels.len() != (height as usize) * (width as usize) {
+                return Err(ImgError::PixelNumberError);
+            }
+            Ok(Image {
                 pixels,
                 height,
                 width,
                 channels,
                 colorspace,
-            };
-            img
+            })
+        }
+
+        /// Returns an [ImageBuilder] with no dimensions or data set yet.
+        pub fn builder() -> ImageBuilder {
+            ImageBuilder::default()
+        }
+
+        /// Creates a `width` × `height` image with every pixel set to `color`.
+        /// Returns [ImgError::DataError] if the pixel buffer would not fit in memory.
+        pub fn solid(
+            width: u32,
+            height: u32,
+            color: Pixel,
+            channels: u8,
+            colorspace: u8,
+        ) -> Result<Image, ImgError> {
+            check_format(channels, colorspace)?;
+            //a Vec cannot hold more than isize::MAX bytes, refuse instead of letting vec! panic
+            let n_pixels: usize = (width as usize)
+                .checked_mul(height as usize)
+                .filter(|n| {
+                    n.checked_mul(core::mem::size_of::<Pixel>())
+                        .is_some_and(|b| b <= isize::MAX as usize)
+                })
+                .ok_or(ImgError::DataError)?;
+            Image::from_pixels(vec![color; n_pixels], width, height, channels, colorspace)
+        }
+
+        /// Creates a `width` × `height` image of transparent black pixels, see [Image::solid].
+        pub fn blank(
+            width: u32,
+            height: u32,
+            channels: u8,
+            colorspace: u8,
+        ) -> Result<Image, ImgError> {
+            Image::solid(width, height, Pixel::new(0, 0, 0, 0), channels, colorspace)
+        }
+
+        pub fn width(&self) -> u32 {
+            self.width
+        }
+
+        pub fn height(&self) -> u32 {
+            self.height
+        }
+
+        /// 3 = RGB, 4 = RGBA
+        pub fn channels(&self) -> u8 {
+            self.channels
+        }
+
+        /// 0 = sRGB with linear alpha, 1 = all channels linear
+        pub fn colorspace(&self) -> u8 {
+            self.colorspace
+        }
+
+        /// Pixels in order left to right, top to bottom.
+        pub fn pixels(&self) -> &[Pixel] {
+            &self.pixels
+        }
+
+        /// Encodes the image as QOI, same as [encode_from_image()].
+        pub fn to_qoi_bytes(&self) -> Vec<u8> {
+            encode_from_image(self)
+        }
+
+        /// Decodes a QOI stream, same as [decode_slice()].
+        pub fn from_qoi_bytes(bytes: &[u8]) -> Result<Image, ImgError> {
+            decode_slice(bytes)
+        }
+
+        /// Iterates over the rows of the image from top to bottom, each a slice of `width` pixels.
+        pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[Pixel]> + ExactSizeIterator {
+            //max(1) keeps chunks from panicking on images without pixels
+            self.pixels.chunks(self.width.max(1) as usize)
+        }
+
+        /// Same as [Image::rows], but yields mutable rows for in-place edits.
+        pub fn rows_mut(
+            &mut self,
+        ) -> impl DoubleEndedIterator<Item = &mut [Pixel]> + ExactSizeIterator {
+            self.pixels.chunks_mut(self.width.max(1) as usize)
+        }
+
+        //Index into self.pixels, None if (x, y) lies outside the image
+        fn pixel_index(&self, x: u32, y: u32) -> Option<usize> {
+            if x < self.width && y < self.height {
+                Some(y as usize * self.width as usize + x as usize)
+            } else {
+                None
+            }
+        }
+
+        /// Returns the pixel at column `x` and row `y`, or `None` if the coordinates lie outside the image.
+        pub fn get_pixel(&self, x: u32, y: u32) -> Option<Pixel> {
+            self.pixel_index(x, y).map(|i| self.pixels[i])
+        }
+
+        /// Replaces the pixel at column `x` and row `y`, returning [ImgError::DataError] if the coordinates lie outside the image.
+        pub fn set_pixel(&mut self, x: u32, y: u32, p: Pixel) -> Result<(), ImgError> {
+            let i: usize = self.pixel_index(x, y).ok_or(ImgError::DataError)?;
+            self.pixels[i] = p;
+            Ok(())
         }
 
         //Expects pixel data in order left to right, top to bottom, with values for rgba in sequential order
         fn pixels_from_bytes(data: Vec<u8>, alpha: bool) -> Result<Vec<Pixel>, ImgError> {
-            let mut pixels: Vec<Pixel> = Vec::with_capacity(data.len() / 4);
             if alpha {
+                let mut pixels: Vec<Pixel> = Vec::with_capacity(data.len() / 4);
                 if data.len() % 4 == 0 {
                     for i in 0..data.len() / 4 {
                         pixels.push(Pixel {
@@ -192,10 +296,14 @@ pub mod qoi_lib {
                     }
                     Ok(pixels)
                 } else {
-                    Err(ImgError::DataError)
+                    Err(ImgError::ByteCountError {
+                        expected_multiple: 4,
+                        got: data.len(),
+                    })
                 }
             } else {
-                if data.len() % 4 == 0 {
+                let mut pixels: Vec<Pixel> = Vec::with_capacity(data.len() / 3);
+                if data.len() % 3 == 0 {
                     for i in 0..data.len() / 3 {
                         pixels.push(Pixel {
                             r: data[i * 3],
@@ -206,37 +314,93 @@ pub mod qoi_lib {
                     }
                     Ok(pixels)
                 } else {
-                    Err(ImgError::DataError)
+                    Err(ImgError::ByteCountError {
+                        expected_multiple: 3,
+                        got: data.len(),
+                    })
                 }
             }
             
         }
+        /// Returns the pixel data interleaved as 3 bytes (RGB) per pixel for 3-channel images and 4 bytes (RGBA) otherwise.
         pub fn to_bytes(&self) -> Vec<u8> {
-            let mut buf: Vec<u8> = Vec::with_capacity(self.height as usize * self.width as usize * 4 as usize);
+            if self.channels == 3 {
+                self.interleave(3)
+            } else {
+                self.interleave(4)
+            }
+        }
+
+        /// Returns the pixel data interleaved as 4 bytes (RGBA) per pixel, regardless of `self.channels`.
+        pub fn to_bytes_rgba(&self) -> Vec<u8> {
+            self.interleave(4)
+        }
+
+        /// Returns the pixel data interleaved as 3 (RGB) or 4 (RGBA) bytes per pixel, regardless of `self.channels`.
+        pub fn to_bytes_with_channels(&self, channels: u8) -> Result<Vec<u8>, ImgError> {
+            if channels != 3 && channels != 4 {
+                return Err(ImgError::DataError);
+            }
+            Ok(self.interleave(channels as usize))
+        }
+
+        fn interleave(&self, channels: usize) -> Vec<u8> {
+            let mut buf: Vec<u8> = Vec::with_capacity(self.pixels.len() * channels);
             for pixel in &self.pixels {
-                buf.push(pixel.r);
-                buf.push(pixel.g);
-                buf.push(pixel.b);
-                buf.push(pixel.a);
-            }
-            return buf;
-        }
-        pub fn write_png(&self, path: &str) {
-            let mut file_path: String = String::new();
-            file_path.push_str(path);
-            if !path.contains(".png") {
-                file_path.push_str(".png");
-            }
-            let path = Path::new(&file_path);
-            let file = match File::create(path) {
-                Ok(f) => f,
-                Err(e) => panic!("ERROR during writing output file: {e:?}")
-            };
-            let buf: Vec<u8> = self.to_bytes();
-            let ref mut w = BufWriter::new(file);
+                buf.extend_from_slice(&pixel.to_array()[..channels]);
+            }
+            buf
+        }
+
+        /// Streams the pixel data into `w` as 3 (RGB) or 4 (RGBA) interleaved bytes per pixel, without building the whole buffer first.
+        /// Any other value for `channels` results in an [std::io::ErrorKind::InvalidInput] error.
+        #[cfg(feature = "std")]
+        pub fn write_raw<W: Write>(&self, w: &mut W, channels: u8) -> std::io::Result<()> {
+            if channels != 3 && channels != 4 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "raw output supports 3 or 4 channels only",
+                ));
+            }
+            let channels: usize = channels as usize;
+            let mut buf: Vec<u8> = Vec::with_capacity(STREAM_BUFFER_SIZE);
+            for pixel in &self.pixels {
+                if buf.len() + channels > STREAM_BUFFER_SIZE {
+                    w.write_all(&buf)?;
+                    buf.clear();
+                }
+                buf.extend_from_slice(&pixel.to_array()[..channels]);
+            }
+            w.write_all(&buf)?;
+            w.flush()
+        }
+
+        /// Writes the image as PNG, RGB for 3-channel images and RGBA otherwise.
+        /// `.png` is appended to `path` unless it already has that extension.
+        #[cfg(feature = "png")]
+        pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<(), ImgError> {
+            let mut file_path: OsString = path.as_ref().as_os_str().to_owned();
+            if path.as_ref().extension() != Some(OsStr::new("png")) {
+                file_path.push(".png");
+            }
+            let file: File = File::create(&file_path).map_err(|e| ImgError::IoError(e.kind()))?;
+            let mut w: BufWriter<File> = BufWriter::new(file);
+            self.write_png_to(&mut w)?;
+            w.flush().map_err(|e| ImgError::IoError(e.kind()))
+        }
+
+        /// Same as [Image::write_png], but writes the PNG stream into `w`.
+        #[cfg(feature = "png")]
+        pub fn write_png_to<W: Write>(&self, w: &mut W) -> Result<(), ImgError> {
             let mut encoder = png::Encoder::new(w, self.width, self.height);
 
-            encoder.set_color(png::ColorType::Rgba);
+            let buf: Vec<u8> = if self.channels == 3 {
+                encoder.set_color(png::ColorType::Rgb);
+                self.to_bytes()
+            } else {
+                encoder.set_color(png::ColorType::Rgba);
+                self.to_bytes_rgba()
+            };
             encoder.set_depth(png::BitDepth::Eight);
 
             encoder.set_source_gamma(png::ScaledFloat::new(1.0 / 2.2));     // 1.0 / 2.2, unscaled, but rounded
@@ -247,764 +411,5225 @@ pub mod qoi_lib {
                 (0.15000, 0.06000)
             );
             encoder.set_source_chromaticities(source_chromaticities);
-            let mut writer = encoder.write_header().unwrap();
-            match writer.write_image_data(&buf) {
-                Ok(_a) => (),
-                Err(e) => panic!("Cannot write output file! {e:?}")
-            }
-            writer.finish().unwrap();
+            let mut writer = encoder.write_header().map_err(png_error)?;
+            writer.write_image_data(&buf).map_err(png_error)?;
+            writer.finish().map_err(png_error)
         }
-    }
 
-    #[derive(Clone, Copy, Debug, PartialEq)]
-    pub struct Pixel {
-        r: u8,
-        g: u8,
-        b: u8,
-        a: u8,
-    }
+        /// Writes the image as binary (P6) PPM, dropping the alpha channel.
+        #[cfg(feature = "std")]
+        pub fn write_ppm<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+            self.write_ppm_to(&mut BufWriter::new(File::create(path)?))
+        }
 
-    #[derive(Debug, PartialEq)]
-    pub enum ChunkType {
-        Run,
-        Index,
-        Luma,
-        Diff,
-        RGB,
-        RGBA,
-    }
+        /// Same as [Image::write_ppm], but writes the PPM stream into `w`.
+        #[cfg(feature = "std")]
+        pub fn write_ppm_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+            write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+            self.write_raw(w, 3)
+        }
 
-    impl Pixel {
-        pub fn new(r: u8, g: u8, b: u8, a: u8) -> Pixel {
-            Pixel { r, g, b, a }
+        /// Writes the image as uncompressed bottom-up BMP, 24-bit for 3-channel images and 32-bit BGRA otherwise.
+        #[cfg(feature = "std")]
+        pub fn write_bmp<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+            self.write_bmp_to(&mut BufWriter::new(File::create(path)?))
         }
-        fn equals(&self, other: &Pixel) -> bool {
-            if (self.r == other.r)
-                && (self.g == other.g)
-                && (self.b == other.b)
-                && (self.a == other.a)
-            {
-                true
-            } else {
-                false
+
+        /// Same as [Image::write_bmp], but writes the BMP stream into `w`.
+        #[cfg(feature = "std")]
+        pub fn write_bmp_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+            let pixel_len: usize = if self.channels == 3 { 3 } else { 4 };
+            //rows are padded to a multiple of 4 bytes
+            let stride: usize = (self.width as usize * pixel_len).div_ceil(4) * 4;
+            let data_len: usize = stride * self.height as usize;
+            let file_len: u32 = u32::try_from(54 + data_len).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "image too large for BMP")
+            })?;
+
+            writer.write_all(b"BM")?;
+            writer.write_all(&file_len.to_le_bytes())?;
+            writer.write_all(&[0, 0, 0, 0])?;
+            writer.write_all(&54u32.to_le_bytes())?;
+            writer.write_all(&40u32.to_le_bytes())?;
+            writer.write_all(&self.width.to_le_bytes())?;
+            writer.write_all(&self.height.to_le_bytes())?;
+            writer.write_all(&1u16.to_le_bytes())?;
+            writer.write_all(&(pixel_len as u16 * 8).to_le_bytes())?;
+            writer.write_all(&0u32.to_le_bytes())?;
+            writer.write_all(&(data_len as u32).to_le_bytes())?;
+            writer.write_all(&[0; 16])?;
+
+            let padding: [u8; 3] = [0; 3];
+            for row in self.rows().rev() {
+                for pixel in row {
+                    writer.write_all(&[pixel.b, pixel.g, pixel.r, pixel.a][..pixel_len])?;
+                }
+                writer.write_all(&padding[..stride - row.len() * pixel_len])?;
             }
+            writer.flush()
         }
 
-        fn equals_rgb(&self, other: &Pixel) -> bool {
-            if (self.r == other.r) && (self.g == other.g) && (self.b == other.b) {
-                true
+        /// Applies `f` to a single [Channel] of every pixel, leaving the remaining channels untouched.
+        pub fn map_channel(&mut self, channel: Channel, f: impl Fn(u8) -> u8) {
+            for pixel in &mut self.pixels {
+                let value: &mut u8 = pixel.channel_mut(channel);
+                *value = f(*value);
+            }
+        }
+
+        /// Estimates how well the image compresses with QOI by running the encoder without producing any output.
+        pub fn compression_advice(&self) -> CompressionAdvice {
+            let encoded_len: usize = exact_encoded_len(self);
+            let mut literal_len: usize = 0;
+            let _ = for_each_chunk(self, |chunk, data, pixel, run| -> Result<(), ()> {
+                if chunk == ChunkType::RGB || chunk == ChunkType::RGBA {
+                    literal_len += chunk_len(&chunk);
+                }
+                Ok(())
+            });
+
+            let raw_len: usize = self.pixels.len() * self.channels as usize;
+            if encoded_len >= raw_len {
+                CompressionAdvice::NotSuitable
+            } else if literal_len * 2 > encoded_len {
+                CompressionAdvice::ConsiderPosterize
             } else {
-                false
+                CompressionAdvice::GoodFit
             }
         }
 
-        //self = curr pixel, other = prev pixel
-        pub fn determine_chunk(
-            &self,
-            other: &Pixel,
-            buffer: &Vec<Pixel>,
-        ) -> (ChunkType, Option<(u8, u8, u8)>) {
-            if self.equals(&other) {
-                return (ChunkType::Run, None);
+        /// Composites every pixel over the solid `background` colour using src-over,
+        /// `out = src * a + background * (1 - a)`, and sets every alpha to 255. The channel count is kept.
+        /// The alpha value of `background` is ignored.
+        pub fn blend_over(&self, background: &Pixel) -> Image {
+            let pixels: Vec<Pixel> = self
+                .pixels
+                .iter()
+                .map(|pixel| pixel.over(background))
+                .collect();
+            Image { pixels, ..*self }
+        }
+
+        /// Composites every pixel over the opaque background colour `bg`, returning an opaque 3-channel image.
+        /// The alpha value of `bg` is ignored.
+        pub fn with_background(&self, bg: Pixel) -> Image {
+            Image {
+                channels: 3,
+                ..self.blend_over(&bg)
             }
+        }
 
-            if self.equals(&buffer[color_hash(&self) as usize]) {
-                return (ChunkType::Index, Some((color_hash(&self), 0, 0)));
+        /// Counts how often each value occurs per channel in a single pass, ordered r, g, b, a and indexed by value.
+        pub fn histogram(&self) -> [[u32; 256]; 4] {
+            let mut counts: [[u32; 256]; 4] = [[0; 256]; 4];
+            for pixel in &self.pixels {
+                for (c, value) in pixel.to_array().into_iter().enumerate() {
+                    counts[c][value as usize] += 1;
+                }
             }
+            counts
+        }
 
-            if self.a != other.a {
-                return (ChunkType::RGBA, None);
+        /// Computes [ChannelStats] for each channel in a single pass, ordered r, g, b, a.
+        /// An image without pixels reports a minimum of 255, a maximum of 0 and a mean of 0.
+        pub fn channel_stats(&self) -> [ChannelStats; 4] {
+            let mut min: [u8; 4] = [u8::MAX; 4];
+            let mut max: [u8; 4] = [u8::MIN; 4];
+            let mut sum: [u64; 4] = [0; 4];
+            for pixel in &self.pixels {
+                for (c, value) in pixel.to_array().into_iter().enumerate() {
+                    min[c] = min[c].min(value);
+                    max[c] = max[c].max(value);
+                    sum[c] += value as u64;
+                }
             }
+            let n: f64 = self.pixels.len().max(1) as f64;
+            core::array::from_fn(|c| ChannelStats {
+                min: min[c],
+                max: max[c],
+                mean: sum[c] as f64 / n,
+            })
+        }
 
-            let diff_tuple: (i16, i16, i16) = self.diff(other);
-            let dr: i16 = diff_tuple.0;
-            let dg: i16 = diff_tuple.1;
-            let db: i16 = diff_tuple.2;
+        /// Converts an sRGB image (colorspace 0) to linear colour values and sets colorspace to 1.
+        /// Uses the piecewise sRGB transfer function; alpha is already linear and left untouched.
+        /// Images that are already linear are returned unchanged.
+        /// Note that 8-bit linear values lose precision in dark tones, so converting back is not lossless.
+        #[cfg(feature = "std")]
+        pub fn to_linear(&self) -> Image {
+            if self.colorspace == 1 {
+                return self.clone();
+            }
+            self.map_transfer(srgb_to_linear, 1)
+        }
 
-            if (dr > -3 && dr < 2) && (dg > -3 && dg < 2) && (db > -3 && db < 2) {
-                let dr: u8 = (dr + DIFF_BIAS as i16) as u8;
-                let dg: u8 = (dg + DIFF_BIAS as i16) as u8;
-                let db: u8 = (db + DIFF_BIAS as i16) as u8;
-                return (ChunkType::Diff, Some((dr, dg, db)));
-            } else if (dg > -33 && dg < 32)
-                && ((dr - dg) > -9)
-                && ((dr - dg) < 8)
-                && ((db - dg) > -9)
-                && ((db - dg) < 8)
-            {
-                let dg_out: u8 = (dg + LUMA_BIAS_G as i16) as u8;
-                let dr_dg: u8 = (dr - dg + LUMA_BIAS_RB as i16) as u8;
-                let db_dg: u8 = (db - dg + LUMA_BIAS_RB as i16) as u8;
-                return (ChunkType::Luma, Some((dg_out, dr_dg, db_dg)));
-            } else {
-                return (ChunkType::RGB, None);
+        /// Converts a linear image (colorspace 1) to sRGB colour values and sets colorspace to 0,
+        /// the inverse of [Image::to_linear]. Images that are already sRGB are returned unchanged.
+        #[cfg(feature = "std")]
+        pub fn to_srgb(&self) -> Image {
+            if self.colorspace == 0 {
+                return self.clone();
             }
+            self.map_transfer(linear_to_srgb, 0)
         }
-        pub fn diff(&self, other: &Pixel) -> (i16, i16, i16) {
-            let mut dr: i16;
-            let dr_inv: i16;
-            let mut dg: i16;
-            let dg_inv: i16;
-            let mut db: i16;
-            let db_inv: i16;
 
-            dr = self.r.wrapping_sub(other.r) as i16;
-            dr_inv = other.r.wrapping_sub(self.r) as i16;
+        /// Multiplies r, g and b of every pixel by its alpha (`c * a / 255`, rounded); fully transparent pixels become all zero.
+        /// Premultiplied alpha is not a QOI concept, the header has no way of marking it,
+        /// so use [Image::unpremultiply] before handing the image to anything expecting straight alpha.
+        pub fn premultiply(&self) -> Image {
+            let mul = |c: u8, a: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+            let pixels: Vec<Pixel> = self
+                .pixels
+                .iter()
+                .map(|p| Pixel::new(mul(p.r, p.a), mul(p.g, p.a), mul(p.b, p.a), p.a))
+                .collect();
+            Image { pixels, ..*self }
+        }
 
-            if dr.abs() > dr_inv.abs() {
-                dr = dr_inv;
-                dr = -dr;
-            }
+        /// Divides r, g and b of every pixel by its alpha, the inverse of [Image::premultiply], clamping to 255.
+        /// Pixels with alpha 0 become all zero. A round trip recovers the original values up to rounding,
+        /// which grows as alpha gets smaller.
+        pub fn unpremultiply(&self) -> Image {
+            let div = |c: u8, a: u8| ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8;
+            let pixels: Vec<Pixel> = self
+                .pixels
+                .iter()
+                .map(|p| match p.a {
+                    0 => Pixel::new(0, 0, 0, 0),
+                    a => Pixel::new(div(p.r, a), div(p.g, a), div(p.b, a), a),
+                })
+                .collect();
+            Image { pixels, ..*self }
+        }
+
+        /// Adds `delta` to r, g and b of every pixel, saturating at 0 and 255. Alpha is left untouched.
+        pub fn adjust_brightness(&self, delta: i16) -> Image {
+            let table: [u8; 256] =
+                core::array::from_fn(|v| (v as i16).saturating_add(delta).clamp(0, 255) as u8);
+            self.map_rgb(&table)
+        }
 
-            dg = self.g.wrapping_sub(other.g) as i16;
-            dg_inv = other.g.wrapping_sub(self.g) as i16;
+        /// Scales r, g and b of every pixel by `factor` around the midpoint 128, rounding and clamping to 0..=255.
+        /// A factor of 1.0 leaves the image unchanged, 0.0 turns every colour grey. Alpha is left untouched.
+        pub fn adjust_contrast(&self, factor: f32) -> Image {
+            let table: [u8; 256] = core::array::from_fn(|v| {
+                let scaled: f32 = (v as f32 - 128.0) * factor + 128.0;
+                (scaled.clamp(0.0, 255.0) + 0.5) as u8
+            });
+            self.map_rgb(&table)
+        }
 
-            if dg.abs() > dg_inv.abs() {
-                dg = dg_inv;
-                dg = -dg;
+        /// Reduces the image to at most `max_colors` colours with median cut and returns the palette together with
+        /// the palette index of every pixel in row-major order. All four channels are quantized, so alpha survives.
+        /// `max_colors` is clamped to `1..=256`, the range a `u8` index can address. Images with no more distinct
+        /// colours than that get exactly their own colours back. The result is deterministic but not optimal.
+        pub fn quantize(&self, max_colors: usize) -> (Vec<Pixel>, Vec<u8>) {
+            if self.pixels.is_empty() {
+                return (Vec::new(), Vec::new());
             }
+            let max_colors: usize = max_colors.clamp(1, 256);
 
-            db = self.b.wrapping_sub(other.b) as i16;
-            db_inv = other.b.wrapping_sub(self.b) as i16;
-
-            if db.abs() > db_inv.abs() {
-                db = db_inv;
-                db = -db;
+            //distinct colours as 0xRRGGBBAA with their number of occurrences, sorted by colour
+            let mut keys: Vec<u32> = self.pixels.iter().map(Pixel::to_u32_rgba).collect();
+            keys.sort_unstable();
+            let mut colors: Vec<(u32, u64)> = Vec::new();
+            for key in keys {
+                match colors.last_mut() {
+                    Some((last, count)) if *last == key => *count += 1,
+                    _ => colors.push((key, 1)),
+                }
             }
 
-            (dr, dg, db)
-        }
-    }
+            let channel = |key: u32, c: usize| key.to_be_bytes()[c];
+            let widest = |colors: &[(u32, u64)]| -> (usize, u8) {
+                (0..4)
+                    .map(|c| {
+                        let values = colors.iter().map(|&(key, _)| channel(key, c));
+                        (c, values.clone().max().unwrap() - values.min().unwrap())
+                    })
+                    .fold(
+                        (0, 0),
+                        |best, next| if next.1 > best.1 { next } else { best },
+                    )
+            };
 
-    //Definition of header bytes
-    struct Header {
-        magic: [char; 4], //magic bytes "qoif"
-        width: u32,       //image width in pixels (BE)
-        height: u32,      //image height in pixels (BE)
-        channels: u8,     // 3 = RGB, 4 = RBGA
-        colorspace: u8,   // 0 = sRGB with linear alpha, 1 = all channels linear
-    }
+            let mut boxes: Vec<Vec<(u32, u64)>> = vec![colors];
+            while boxes.len() < max_colors {
+                //split the box with the largest extent in any channel, the first one on ties
+                let mut split: Option<(usize, usize, u8)> = None;
+                for (i, colors) in boxes.iter().enumerate() {
+                    let (c, extent) = widest(colors);
+                    if extent > 0 && split.is_none_or(|(_, _, best)| extent > best) {
+                        split = Some((i, c, extent));
+                    }
+                }
+                let Some((i, c, _)) = split else {
+                    break;
+                };
 
-    impl Header {
-        fn convert_to_bytestream(&self) -> [u8; 14] {
-            let mut out: [u8; 14] = [0; 14];
+                let colors: &mut Vec<(u32, u64)> = &mut boxes[i];
+                colors.sort_by_key(|&(key, _)| (channel(key, c), key));
+                //cut at the median pixel, keeping at least one colour on either side
+                let half: u64 = colors.iter().map(|&(_, count)| count).sum::<u64>() / 2;
+                let mut seen: u64 = 0;
+                let mut cut: usize = colors
+                    .iter()
+                    .position(|&(_, count)| {
+                        seen += count;
+                        seen > half
+                    })
+                    .unwrap_or(0);
+                cut = cut.clamp(1, colors.len() - 1);
+                let upper: Vec<(u32, u64)> = colors.split_off(cut);
+                boxes.insert(i + 1, upper);
+            }
 
-            //First, set magic bytes
-            out[0] = self.magic[0] as u8;
-            out[1] = self.magic[1] as u8;
-            out[2] = self.magic[2] as u8;
-            out[3] = self.magic[3] as u8;
+            //each box becomes the mean of its colours weighted by their counts
+            let palette: Vec<Pixel> = boxes
+                .iter()
+                .map(|colors| {
+                    let total: u64 = colors.iter().map(|&(_, count)| count).sum();
+                    let mean = |c: usize| {
+                        let sum: u64 = colors
+                            .iter()
+                            .map(|&(key, count)| channel(key, c) as u64 * count)
+                            .sum();
+                        ((sum + total / 2) / total) as u8
+                    };
+                    Pixel::new(mean(0), mean(1), mean(2), mean(3))
+                })
+                .collect();
 
-            //split width and height into 8-bit chunks
-            let width_bytes = self.width.to_be_bytes();
-            let height_bytes = self.height.to_be_bytes();
+            let mut lookup: Vec<(u32, u8)> = boxes
+                .iter()
+                .enumerate()
+                .flat_map(|(i, colors)| colors.iter().map(move |&(key, _)| (key, i as u8)))
+                .collect();
+            lookup.sort_unstable();
+            let indices: Vec<u8> = self
+                .pixels
+                .iter()
+                .map(|pixel| {
+                    let key: u32 = pixel.to_u32_rgba();
+                    let slot: usize = lookup.binary_search_by_key(&key, |&(k, _)| k).unwrap();
+                    lookup[slot].1
+                })
+                .collect();
+            (palette, indices)
+        }
 
-            out[4] = width_bytes[0];
-            out[5] = width_bytes[1];
-            out[6] = width_bytes[2];
-            out[7] = width_bytes[3];
-            out[8] = height_bytes[0];
-            out[9] = height_bytes[1];
-            out[10] = height_bytes[2];
-            out[11] = height_bytes[3];
+        /// Returns up to `k` dominant colours with the fraction of pixels they cover, largest coverage first.
+        /// The colours are the centroids of [Image::quantize] with `k` colours, so an image with at most `k`
+        /// distinct colours gets its exact colours back, and `k` is capped at 256. Empty images and `k = 0`
+        /// return no colours.
+        pub fn dominant_colors(&self, k: usize) -> Vec<(Pixel, f32)> {
+            if k == 0 || self.pixels.is_empty() {
+                return Vec::new();
+            }
+            let (palette, indices) = self.quantize(k);
+            let mut counts: Vec<usize> = vec![0; palette.len()];
+            for &index in &indices {
+                counts[index as usize] += 1;
+            }
+            let total: f32 = indices.len() as f32;
+            let mut colors: Vec<(Pixel, f32)> = palette
+                .into_iter()
+                .zip(counts)
+                .map(|(color, count)| (color, count as f32 / total))
+                .collect();
+            colors.sort_by(|a, b| b.1.total_cmp(&a.1));
+            colors
+        }
 
-            //Set information bits
-            out[12] = self.channels;
-            out[13] = self.colorspace;
+        /// Maps every pixel to its nearest `palette` entry (all four channels, squared distance) with
+        /// Floyd-Steinberg error diffusion: the error of each pixel is spread over its right and lower neighbours,
+        /// 7/16 right, 3/16 lower left, 5/16 below and 1/16 lower right, and dropped where a neighbour lies outside
+        /// the image. The result only contains palette colours. An empty palette returns the image unchanged.
+        pub fn dither_to_palette(&self, palette: &[Pixel]) -> Image {
+            if palette.is_empty() {
+                return self.clone();
+            }
+            let width: usize = self.width as usize;
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(self.pixels.len());
+            //accumulated error per channel for the current and the next row
+            let mut errors: Vec<[i32; 4]> = vec![[0; 4]; width];
+            let mut next_errors: Vec<[i32; 4]> = vec![[0; 4]; width];
+            for row in self.pixels.chunks(width.max(1)) {
+                for (x, pixel) in row.iter().enumerate() {
+                    let wanted: [i32; 4] = core::array::from_fn(|c| {
+                        (pixel.to_array()[c] as i32 + errors[x][c]).clamp(0, 255)
+                    });
+                    let [r, g, b, a] = wanted.map(|v| v as u8);
+                    let chosen: Pixel = *Pixel::new(r, g, b, a).nearest(palette).unwrap();
+                    let error: [i32; 4] =
+                        core::array::from_fn(|c| wanted[c] - chosen.to_array()[c] as i32);
 
-            out
+                    let spread = |target: &mut [i32; 4], weight: i32| {
+                        for c in 0..4 {
+                            target[c] += error[c] * weight / 16;
+                        }
+                    };
+                    if x + 1 < width {
+                        spread(&mut errors[x + 1], 7);
+                        spread(&mut next_errors[x + 1], 1);
+                    }
+                    if x > 0 {
+                        spread(&mut next_errors[x - 1], 3);
+                    }
+                    spread(&mut next_errors[x], 5);
+                    pixels.push(chosen);
+                }
+                core::mem::swap(&mut errors, &mut next_errors);
+                next_errors.fill([0; 4]);
+            }
+            Image { pixels, ..*self }
         }
-    }
 
-    //Definition of End of Stream bytes
-    #[derive(Debug)]
-    struct End {
-        bytes: [u8; 8],
-    }
-    impl End {
-        fn new() -> End {
-            End {
-                bytes: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+        //Applies the transfer function f, operating on values in 0..=1, to r, g and b via a lookup table
+        #[cfg(feature = "std")]
+        fn map_transfer(&self, f: fn(f64) -> f64, colorspace: u8) -> Image {
+            let table: [u8; 256] =
+                core::array::from_fn(|v| (f(v as f64 / 255.0) * 255.0).round() as u8);
+            Image {
+                colorspace,
+                ..self.map_rgb(&table)
             }
         }
-    }
 
-    //chunks as defined in the QOI spec
-    const QOI_OP_RGB: u8 = 0b1111_1110;
-    const QOI_OP_RGBA: u8 = 0b1111_1111;
-    const QOI_OP_RUN: u8 = 0b1100_0000;
-    const QOI_OP_INDEX: u8 = 0b0000_0000;
-    const QOI_OP_DIFF: u8 = 0b0100_0000;
-    const QOI_OP_LUMA: u8 = 0b1000_0000;
-
-    //Biases as defined in the QOI spec
-    const RUN_BIAS: u8 = 1;
-
-    const DIFF_BIAS: u8 = 2;
-
-    const LUMA_BIAS_G: u8 = 32;
-    const LUMA_BIAS_RB: u8 = 8;
+        //Looks up r, g and b of every pixel in `table`, keeping alpha
+        fn map_rgb(&self, table: &[u8; 256]) -> Image {
+            let pixels: Vec<Pixel> = self
+                .pixels
+                .iter()
+                .map(|p| {
+                    Pixel::new(
+                        table[p.r as usize],
+                        table[p.g as usize],
+                        table[p.b as usize],
+                        p.a,
+                    )
+                })
+                .collect();
+            Image { pixels, ..*self }
+        }
 
-    //hash function for assigning buffer indices to stored pixels
-    fn color_hash(pixel: &Pixel) -> u8 {
-        let store: u32 =
-            pixel.r as u32 * 3 + pixel.g as u32 * 5 + pixel.b as u32 * 7 + pixel.a as u32 * 11;
-        (store % 64) as u8
-    }
+        /// Computes the per-channel absolute difference of r, g and b against `other` as an opaque image,
+        /// black where both images agree. Returns [ImgError::DataError] if the dimensions differ.
+        /// Differences in alpha are not visible in the result, see [Image::max_channel_diff].
+        pub fn diff(&self, other: &Image) -> Result<Image, ImgError> {
+            if (self.width, self.height) != (other.width, other.height) {
+                return Err(ImgError::DataError);
+            }
+            let pixels: Vec<Pixel> = self
+                .pixels
+                .iter()
+                .zip(&other.pixels)
+                .map(|(p, q)| {
+                    Pixel::new(p.r.abs_diff(q.r), p.g.abs_diff(q.g), p.b.abs_diff(q.b), 255)
+                })
+                .collect();
+            Ok(Image { pixels, ..*self })
+        }
 
-    pub fn encode_from_image(img: Image) -> Vec<u8> {
-        let mut prev_pixel: Pixel = Pixel {
-            r: 0u8,
-            b: 0u8,
-            g: 0u8,
-            a: 255u8,
-        };
+        /// Returns the largest absolute difference of any single channel, alpha included, between the two images,
+        /// e.g. to assert that a lossy operation stays within a tolerance. Images of different dimensions yield 255.
+        pub fn max_channel_diff(&self, other: &Image) -> u8 {
+            if (self.width, self.height) != (other.width, other.height) {
+                return u8::MAX;
+            }
+            self.pixels
+                .iter()
+                .zip(&other.pixels)
+                .flat_map(|(p, q)| p.to_array().into_iter().zip(q.to_array()))
+                .map(|(c, d)| c.abs_diff(d))
+                .max()
+                .unwrap_or(0)
+        }
 
-        let mut prev_buffer: Vec<Pixel> = Vec::with_capacity(64);
+        //Returns the pixel at (x, y), with coordinates outside the image clamped to the nearest edge
+        fn clamped_pixel(&self, x: i64, y: i64) -> &Pixel {
+            let x: i64 = x.clamp(0, self.width as i64 - 1);
+            let y: i64 = y.clamp(0, self.height as i64 - 1);
+            &self.pixels[(y * self.width as i64 + x) as usize]
+        }
 
-        for i in 0..64 {
-            let pix: Pixel = Pixel {
-                r: 0,
-                g: 0,
-                b: 0,
-                a: 0,
-            };
-            prev_buffer.push(pix);
+        /// Convolves the colour channels with a 3×3 `kernel`, each result being divided by `divisor` and offset by `bias`.
+        /// Pixels outside the image are clamped to the nearest edge; alpha is passed through unchanged.
+        #[cfg(feature = "std")]
+        pub fn apply_kernel(&self, kernel: [[f32; 3]; 3], divisor: f32, bias: f32) -> Image {
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(self.pixels.len());
+            for y in 0..self.height as i64 {
+                for x in 0..self.width as i64 {
+                    let mut sum: [f32; 3] = [0.0; 3];
+                    for (ky, row) in kernel.iter().enumerate() {
+                        for (kx, weight) in row.iter().enumerate() {
+                            let pix: &Pixel =
+                                self.clamped_pixel(x + kx as i64 - 1, y + ky as i64 - 1);
+                            sum[0] += weight * pix.r as f32;
+                            sum[1] += weight * pix.g as f32;
+                            sum[2] += weight * pix.b as f32;
+                        }
+                    }
+                    let [r, g, b] =
+                        sum.map(|c| (c / divisor + bias).round().clamp(0.0, 255.0) as u8);
+                    pixels.push(Pixel::new(r, g, b, self.clamped_pixel(x, y).a));
+                }
+            }
+            Image { pixels, ..*self }
         }
 
-        let mut encoded_bytes: Vec<u8> = Vec::new();
-        let mut run: u64 = 0;
+        /// Replaces each pixel's colour with the mean of the (2 * `radius` + 1)² square around it.
+        /// Pixels outside the image are clamped to the nearest edge; alpha is passed through unchanged.
+        /// `radius` is limited to the larger of width and height.
+        pub fn blur_box(&self, radius: u32) -> Image {
+            let radius: i64 = radius.min(self.width.max(self.height)) as i64;
+            let area: u64 = ((2 * radius + 1) * (2 * radius + 1)) as u64;
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(self.pixels.len());
+            for y in 0..self.height as i64 {
+                for x in 0..self.width as i64 {
+                    let mut sum: [u64; 3] = [0; 3];
+                    for dy in -radius..=radius {
+                        for dx in -radius..=radius {
+                            let pix: &Pixel = self.clamped_pixel(x + dx, y + dy);
+                            sum[0] += pix.r as u64;
+                            sum[1] += pix.g as u64;
+                            sum[2] += pix.b as u64;
+                        }
+                    }
+                    let [r, g, b] = sum.map(|c| ((c + area / 2) / area) as u8);
+                    pixels.push(Pixel::new(r, g, b, self.clamped_pixel(x, y).a));
+                }
+            }
+            Image { pixels, ..*self }
+        }
 
-        let head = Header {
-            magic: ['q', 'o', 'i', 'f'],
-            width: img.width,
-            height: img.height,
-            channels: img.channels,
-            colorspace: img.colorspace,
-        };
-        let head_stream = head.convert_to_bytestream();
+        /// Scales the image to `new_width` × `new_height` using nearest-neighbour sampling.
+        /// A zero width or height results in an image without pixels. Scaling up an image without pixels
+        /// has nothing to sample from and fills the result with transparent black.
+        pub fn resize(&self, new_width: u32, new_height: u32) -> Image {
+            let n_pixels: usize = new_width as usize * new_height as usize;
+            if self.pixels.is_empty() {
+                return Image {
+                    pixels: vec![Pixel::new(0, 0, 0, 0); n_pixels],
+                    width: new_width,
+                    height: new_height,
+                    ..*self
+                };
+            }
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(n_pixels);
+            for y in 0..new_height as u64 {
+                let src_y: u64 = y * self.height as u64 / new_height as u64;
+                for x in 0..new_width as u64 {
+                    let src_x: u64 = x * self.width as u64 / new_width as u64;
+                    pixels.push(self.pixels[(src_y * self.width as u64 + src_x) as usize]);
+                }
+            }
+            Image {
+                pixels,
+                width: new_width,
+                height: new_height,
+                ..*self
+            }
+        }
 
-        for i in head_stream {
-            encoded_bytes.push(i);
+        /// Copies the `w` × `h` rectangle whose top left corner lies at column `x` and row `y` into a new image.
+        /// Returns [ImgError::DataError] if the rectangle exceeds the image bounds.
+        pub fn crop(&self, x: u32, y: u32, w: u32, h: u32) -> Result<Image, ImgError> {
+            match (x.checked_add(w), y.checked_add(h)) {
+                (Some(right), Some(bottom)) if right <= self.width && bottom <= self.height => (),
+                _ => return Err(ImgError::DataError),
+            }
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(w as usize * h as usize);
+            for row in y..y + h {
+                let start: usize = row as usize * self.width as usize + x as usize;
+                pixels.extend_from_slice(&self.pixels[start..start + w as usize]);
+            }
+            Ok(Image {
+                pixels,
+                width: w,
+                height: h,
+                ..*self
+            })
         }
 
-        let mut counter: u64 = 0;
+        /// Mirrors the image along its vertical axis, reversing each row.
+        pub fn flip_horizontal(&self) -> Image {
+            let mut img: Image = self.clone();
+            for row in img.rows_mut() {
+                row.reverse();
+            }
+            img
+        }
 
-        for pixel in img.pixels {
-            counter += 1;
-            let chunk: (ChunkType, Option<(u8, u8, u8)>) =
-                pixel.determine_chunk(&prev_pixel, &prev_buffer);
-            if chunk == (ChunkType::Run, None) {
-                run += 1;
-                prev_pixel = pixel.clone();
-                continue;
+        /// Mirrors the image along its horizontal axis, reversing the order of the rows.
+        pub fn flip_vertical(&self) -> Image {
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(self.pixels.len());
+            for row in self.rows().rev() {
+                pixels.extend_from_slice(row);
             }
-            if run > 0 {
-                if run > 62 {
-                    while run > 0 {
-                        if run / 62 > 0 {
-                            encoded_bytes.push(QOI_OP_RUN | (62 - RUN_BIAS));
-                            run -= 62;
-                        } else if run % 62 > 0 {
-                            let run_remainder: u8 = run.try_into().unwrap();
-                            encoded_bytes.push(QOI_OP_RUN | (run_remainder - RUN_BIAS));
-                            run = 0;
-                        } else {
-                            break;
-                        }
-                    }
-                } else {
-                    let run8: u8 = run.try_into().unwrap();
-                    encoded_bytes.push(QOI_OP_RUN | (run8 - RUN_BIAS));
-                    run = 0;
+            Image { pixels, ..*self }
+        }
+
+        /// Rotates the image by 90 degrees clockwise, swapping width and height.
+        pub fn rotate90(&self) -> Image {
+            let (w, h): (usize, usize) = (self.width as usize, self.height as usize);
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(self.pixels.len());
+            for y in 0..w {
+                for x in 0..h {
+                    pixels.push(self.pixels[(h - 1 - x) * w + y]);
                 }
             }
+            Image {
+                pixels,
+                width: self.height,
+                height: self.width,
+                ..*self
+            }
+        }
 
+        /// Rotates the image by 180 degrees.
+        pub fn rotate180(&self) -> Image {
+            let pixels: Vec<Pixel> = self.pixels.iter().rev().copied().collect();
+            Image { pixels, ..*self }
+        }
 
-            match chunk {
-                (ChunkType::Index, Some((index, irr1, irr2))) => {
-                    encoded_bytes.push(QOI_OP_INDEX | index);
-                    prev_pixel = pixel;
-                }
-                (ChunkType::Diff, Some((dr, dg, db))) => {
-                    let mut out: u8 = 0b0000_0000;
-                    out = out | db;
-                    out = out | (dg << 2);
-                    out = out | (dr << 4);
-                    encoded_bytes.push(QOI_OP_DIFF | out);
-                    prev_pixel = pixel.clone();
-                    prev_buffer[color_hash(&pixel) as usize] = pixel;
-                }
-                (ChunkType::Luma, Some((dg, dr_dg, db_dg))) => {
-                    let mut out: [u8; 2] = [0b0000_0000; 2];
-                    out[0] |= dg;
-                    out[0] |= QOI_OP_LUMA;
-                    out[1] |= db_dg;
-                    out[1] |= dr_dg << 4;
-                    encoded_bytes.push(out[0]);
-                    encoded_bytes.push(out[1]);
-                    prev_pixel = pixel.clone();
-                    prev_buffer[color_hash(&pixel) as usize] = pixel;
+        /// Rotates the image by 270 degrees clockwise (90 degrees counter-clockwise), swapping width and height.
+        pub fn rotate270(&self) -> Image {
+            let (w, h): (usize, usize) = (self.width as usize, self.height as usize);
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(self.pixels.len());
+            for y in 0..w {
+                for x in 0..h {
+                    pixels.push(self.pixels[x * w + (w - 1 - y)]);
                 }
-                (ChunkType::RGB, None) => {
-                    encoded_bytes.push(QOI_OP_RGB);
-                    encoded_bytes.push(pixel.r);
-                    encoded_bytes.push(pixel.g);
-                    encoded_bytes.push(pixel.b);
-                    prev_pixel = pixel.clone();
-                    prev_buffer[color_hash(&pixel) as usize] = pixel;
-                }
-                (ChunkType::RGBA, None) => {
-                    if (pixel.a as i16 - prev_pixel.a as i16) == 0i16 {
-                        //this should never be reached, but it is
-                        encoded_bytes.push(QOI_OP_RGB);
-                        encoded_bytes.push(pixel.r);
-                        encoded_bytes.push(pixel.g);
-                        encoded_bytes.push(pixel.b);
-                        prev_pixel = pixel.clone();
-                        prev_buffer[color_hash(&pixel) as usize] = pixel;
-                    } else {
-                        encoded_bytes.push(QOI_OP_RGBA);
-                        encoded_bytes.push(pixel.r);
-                        encoded_bytes.push(pixel.g);
-                        encoded_bytes.push(pixel.b);
-                        encoded_bytes.push(pixel.a);
-                        prev_pixel = pixel.clone();
-                        prev_buffer[color_hash(&pixel) as usize] = pixel;
-                    }
-                }
-                _ => panic!(
-                    "Critical error at encoding stage: Illegal output from difference function."
-                ),
+            }
+            Image {
+                pixels,
+                width: self.height,
+                height: self.width,
+                ..*self
             }
         }
 
-        if run > 0 {
-            if run > 62 {
-                while run > 0 {
-                    if run / 62 > 0 {
-                        encoded_bytes.push(QOI_OP_RUN | (62 - RUN_BIAS));
-                        run -= 62;
-                    } else if run % 62 > 0 {
-                        let run_remainder: u8 = run.try_into().unwrap();
-                        encoded_bytes.push(QOI_OP_RUN | (run_remainder - RUN_BIAS));
-                        run = 0;
-                    } else {
-                        break;
-                    }
-                }
-            } else {
-                let run8: u8 = run.try_into().unwrap();
-                encoded_bytes.push(QOI_OP_RUN | (run8 - RUN_BIAS));
-                // run = 0;
+        /// Splits a horizontal sprite sheet into `width / frame_width` frames of full height, left to right.
+        /// Returns [ImgError::DataError] if `frame_width` is zero or does not evenly divide the image width.
+        pub fn extract_frames(&self, frame_width: u32) -> Result<Vec<Image>, ImgError> {
+            if frame_width == 0 || self.width % frame_width != 0 {
+                return Err(ImgError::DataError);
+            }
+            let n_frames: u32 = self.width / frame_width;
+            let mut frames: Vec<Image> = Vec::with_capacity(n_frames as usize);
+            for frame in 0..n_frames {
+                frames.push(self.crop(frame * frame_width, 0, frame_width, self.height)?);
             }
+            Ok(frames)
         }
+    }
 
-        let end_bytes = End::new();
-        for i in end_bytes.bytes {
-            encoded_bytes.push(i)
+    //Only 3 or 4 channels and colorspace 0 or 1 are defined by the QOI format
+    fn check_format(channels: u8, colorspace: u8) -> Result<(), ImgError> {
+        if !(3..=4).contains(&channels) || colorspace > 1 {
+            return Err(ImgError::InvalidFormat {
+                channels,
+                colorspace,
+            });
         }
-
-        info!("Number of pixels processed: {}.", counter);
-        info!(
-            "Number of bytes in encoding: {:?}.",
-            encoded_bytes.len() - 22
-        );
-        info!(
-            "Compression rate: {:.2}%.",
-            (1.0 - (encoded_bytes.len() - 22) as f64 / (counter * 4) as f64) * 100.0
-        );
-
-        encoded_bytes
+        Ok(())
     }
-    /// Writes Image as byte vector to file with name given as string slice.
+
+    /// Builds an [Image] from named settings, created by [Image::builder()].
+    /// `channels` defaults to 4 and `colorspace` to 0; width, height and pixel data must be given.
     /// ```rust
     /// # use qoi::qoi_lib::*;
-    /// # fn main() {
-    /// 
-    /// let bytes: Vec<u8> = vec![];
-    /// let name = "qoi-image";
-    /// write_to_file(bytes, name);
-    /// #
-    /// # 
-    /// # }
+    /// let img: Image = Image::builder()
+    ///     .width(3)
+    ///     .height(2)
+    ///     .channels(3)
+    ///     .rgba_bytes(vec![255; 3 * 2 * 4])
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!((img.width(), img.height()), (3, 2));
     /// ```
-    pub fn write_to_file(bytes: Vec<u8>, filename: &str) -> std::io::Result<()> {
-        let mut file_path: String = String::from(filename);
-        if !filename.contains(".qoi") {
-            file_path.push_str(".qoi");
+    #[derive(Default, Clone)]
+    pub struct ImageBuilder {
+        width: Option<u32>,
+        height: Option<u32>,
+        channels: Option<u8>,
+        colorspace: Option<u8>,
+        data: Option<BuilderData>,
+    }
+
+    //Pixel data given to an ImageBuilder
+    #[derive(Clone)]
+    enum BuilderData {
+        RgbaBytes(Vec<u8>),
+        Pixels(Vec<Pixel>),
+    }
+
+    impl ImageBuilder {
+        pub fn width(mut self, width: u32) -> ImageBuilder {
+            self.width = Some(width);
+            self
         }
 
-        let mut buffer = File::create(file_path)?;
-        let mut pos = 0;
+        pub fn height(mut self, height: u32) -> ImageBuilder {
+            self.height = Some(height);
+            self
+        }
 
-        while pos < bytes.len() {
-            let bytes_written = buffer.write(&bytes[pos..])?;
-            pos += bytes_written;
+        /// 3 (RGB) or 4 (RGBA).
+        pub fn channels(mut self, channels: u8) -> ImageBuilder {
+            self.channels = Some(channels);
+            self
+        }
+
+        /// 0 = sRGB with linear alpha, 1 = all channels linear.
+        pub fn colorspace(mut self, colorspace: u8) -> ImageBuilder {
+            self.colorspace = Some(colorspace);
+            self
+        }
+
+        /// Pixel data as 4 bytes (RGBA) per pixel, left to right, top to bottom, regardless of `channels`.
+        /// Replaces data given by [ImageBuilder::pixels()].
+        pub fn rgba_bytes(mut self, bytes: Vec<u8>) -> ImageBuilder {
+            self.data = Some(BuilderData::RgbaBytes(bytes));
+            self
+        }
+
+        /// Pixel data, left to right, top to bottom. Replaces data given by [ImageBuilder::rgba_bytes()].
+        pub fn pixels(mut self, pixels: Vec<Pixel>) -> ImageBuilder {
+            self.data = Some(BuilderData::Pixels(pixels));
+            self
+        }
+
+        /// Returns [ImgError::DataError] if width, height or pixel data are missing, [ImgError::InvalidFormat] if
+        /// `channels` or `colorspace` are out of range, [ImgError::ByteCountError] if the RGBA bytes do not divide into
+        /// pixels and [ImgError::PixelNumberError] if the number of pixels does not match width and height.
+        pub fn build(self) -> Result<Image, ImgError> {
+            let (Some(width), Some(height), Some(data)) = (self.width, self.height, self.data)
+            else {
+                return Err(ImgError::DataError);
+            };
+            let channels: u8 = self.channels.unwrap_or(4);
+            let colorspace: u8 = self.colorspace.unwrap_or(0);
+            let pixels: Vec<Pixel> = match data {
+                BuilderData::RgbaBytes(bytes) => Image::pixels_from_bytes(bytes, true)?,
+                BuilderData::Pixels(pixels) => pixels,
+            };
+            Image::from_pixels(pixels, width, height, channels, colorspace)
         }
-        Ok(())
     }
 
-    fn read_header(bytes: &[u8]) -> Result<(u32, u32, u8, u8), ImgError> {
-        if bytes[0] == 'q' as u8
-            && bytes[1] == 'o' as u8
-            && bytes[2] == 'i' as u8
-            && bytes[3] == 'f' as u8
-        {
-            let mut width: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0000;
-            let mut height: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0000;
-            width |= ((bytes[4] as u32) << 24) as u32;
-            width |= ((bytes[5] as u32) << 16) as u32;
-            width |= ((bytes[6] as u32) << 8) as u32;
-            width |= (bytes[7]) as u32;
-            height |= ((bytes[8] as u32) << 24) as u32;
-            height |= ((bytes[9] as u32) << 16) as u32;
-            height |= ((bytes[10] as u32) << 8) as u32;
-            height |= (bytes[11]) as u32;
-            return Ok((width, height, bytes[12], bytes[13]));
+    /// Decodes the bytes as a QOI stream, same as [decode()]. The reverse of `Vec::<u8>::from(image)`.
+    impl TryFrom<Vec<u8>> for Image {
+        type Error = ImgError;
+
+        fn try_from(bytes: Vec<u8>) -> Result<Image, ImgError> {
+            decode(bytes)
+        }
+    }
+
+    /// Encodes the image as a QOI stream, same as [encode_from_image()]. Use `Image::try_from(bytes)` to decode it again.
+    impl From<Image> for Vec<u8> {
+        fn from(img: Image) -> Vec<u8> {
+            encode_from_image(&img)
+        }
+    }
+
+    //sRGB electro-optical transfer function (IEC 61966-2-1), c in 0..=1
+    #[cfg(feature = "std")]
+    fn srgb_to_linear(c: f64) -> f64 {
+        if c <= 0.04045 {
+            c / 12.92
         } else {
-            return Err(ImgError::HeaderError);
+            ((c + 0.055) / 1.055).powf(2.4)
         }
     }
 
-    fn read_tag(tag: u8) -> Result<ChunkType, ImgError> {
-        if tag == QOI_OP_RGB {
-            return Ok(ChunkType::RGB);
+    //Inverse of srgb_to_linear
+    #[cfg(feature = "std")]
+    fn linear_to_srgb(c: f64) -> f64 {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
         }
-        if tag == QOI_OP_RGBA {
-            return Ok(ChunkType::RGBA);
+    }
+
+    //maps failures of the png encoder onto ImgError
+    #[cfg(feature = "png")]
+    fn png_error(e: png::EncodingError) -> ImgError {
+        match e {
+            png::EncodingError::IoError(e) => ImgError::IoError(e.kind()),
+            _ => ImgError::IoError(std::io::ErrorKind::InvalidData),
         }
-        if (tag & 0b1100_0000) == QOI_OP_DIFF {
-            return Ok(ChunkType::Diff);
+    }
+
+    #[cfg(feature = "image")]
+    impl Image {
+        /// Converts any [image::DynamicImage] into an [Image], keeping the alpha channel only if the source has one.
+        /// Colour values are converted to 8 bits per channel and interpreted as sRGB.
+        pub fn from_dynamic_image(src: &image::DynamicImage) -> Image {
+            let rgba: image::RgbaImage = src.to_rgba8();
+            let pixels: Vec<Pixel> = rgba
+                .pixels()
+                .map(|p| Pixel::new(p[0], p[1], p[2], p[3]))
+                .collect();
+            Image {
+                pixels,
+                height: rgba.height(),
+                width: rgba.width(),
+                channels: if src.color().has_alpha() { 4 } else { 3 },
+                colorspace: 0,
+            }
         }
-        if (tag & 0b1100_0000) == QOI_OP_INDEX {
-            return Ok(ChunkType::Index);
+    }
+
+    /// Alpha is set to 255 for 3-channel images.
+    #[cfg(feature = "image")]
+    impl From<&Image> for image::RgbaImage {
+        fn from(img: &Image) -> image::RgbaImage {
+            let mut bytes: Vec<u8> = img.to_bytes_rgba();
+            if img.channels == 3 {
+                for pixel in bytes.chunks_exact_mut(4) {
+                    pixel[3] = 255;
+                }
+            }
+            //the buffer always holds width * height * 4 bytes
+            image::RgbaImage::from_raw(img.width, img.height, bytes).unwrap()
         }
-        if (tag & 0b1100_0000) == QOI_OP_LUMA {
-            return Ok(ChunkType::Luma);
+    }
+
+    /// Produces an RGB buffer for 3-channel images and an RGBA buffer otherwise.
+    #[cfg(feature = "image")]
+    impl From<&Image> for image::DynamicImage {
+        fn from(img: &Image) -> image::DynamicImage {
+            if img.channels == 3 {
+                //the buffer always holds width * height * 3 bytes
+                let rgb: image::RgbImage =
+                    image::RgbImage::from_raw(img.width, img.height, img.to_bytes()).unwrap();
+                image::DynamicImage::ImageRgb8(rgb)
+            } else {
+                image::DynamicImage::ImageRgba8(image::RgbaImage::from(img))
+            }
         }
-        if (tag & 0b1100_0000) == QOI_OP_RUN {
-            return Ok(ChunkType::Run);
+    }
+
+    //Serialized form of Image, deserialized into a plain struct first so the pixel count can be validated
+    #[cfg(feature = "serde")]
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(rename = "Image")]
+    struct SerdeImage<P> {
+        width: u32,
+        height: u32,
+        channels: u8,
+        colorspace: u8,
+        pixels: P,
+    }
+
+    #[cfg(feature = "serde")]
+    impl serde::Serialize for Image {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            SerdeImage {
+                width: self.width,
+                height: self.height,
+                channels: self.channels,
+                colorspace: self.colorspace,
+                pixels: &self.pixels,
+            }
+            .serialize(serializer)
         }
-        return Err(ImgError::DecodeError);
     }
 
-    fn dec_rgb(bytes: &[u8], alpha: u8) -> Pixel {
-        let pixel: Pixel = Pixel::new(bytes[1], bytes[2], bytes[3], alpha);
-        pixel
+    /// Fails if the number of pixels does not match `width * height`.
+    #[cfg(feature = "serde")]
+    impl<'de> serde::Deserialize<'de> for Image {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Image, D::Error> {
+            let img: SerdeImage<Vec<Pixel>> = SerdeImage::deserialize(deserializer)?;
+            Image::from_pixels(
+                img.pixels,
+                img.width,
+                img.height,
+                img.channels,
+                img.colorspace,
+            )
+            .map_err(serde::de::Error::custom)
+        }
     }
 
-    fn dec_rgba(bytes: &[u8]) -> Pixel {
-        let pixel: Pixel = Pixel::new(bytes[1], bytes[2], bytes[3], bytes[4]);
-        pixel
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Pixel {
+        r: u8,
+        g: u8,
+        b: u8,
+        a: u8,
     }
 
-    fn dec_diff(byte: u8, prev_pixel: &Pixel) -> Pixel {
-        let dr: u8;
-        let dg: u8;
-        let db: u8;
+    /// Selects one of the four channels stored in a [Pixel].
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Channel {
+        Red,
+        Green,
+        Blue,
+        Alpha,
+    }
 
-        dr = (byte & 0b00110000) >> 4;
-        dg = (byte & 0b00001100) >> 2;
-        db = byte & 0b00000011;
+    /// Assessment of how well an image suits QOI, as returned by [Image::compression_advice].
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum CompressionAdvice {
+        /// Runs, index hits and small differences make up most of the encoding.
+        GoodFit,
+        /// Literal RGB(A) chunks take up more than half of the encoding; reducing the number of colours would help.
+        ConsiderPosterize,
+        /// The encoding would be at least as large as the raw pixel data.
+        NotSuitable,
+    }
 
-        let r: u8 = prev_pixel.r.wrapping_add(dr);
-        let g: u8 = prev_pixel.g.wrapping_add(dg);
-        let b: u8 = prev_pixel.b.wrapping_add(db);
+    /// Minimum, maximum and mean value of a single channel, see [Image::channel_stats].
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ChannelStats {
+        pub min: u8,
+        pub max: u8,
+        pub mean: f64,
+    }
 
-        let r: u8 = r.wrapping_sub(DIFF_BIAS);
-        let b: u8 = b.wrapping_sub(DIFF_BIAS);
-        let g: u8 = g.wrapping_sub(DIFF_BIAS);
+    /// Sizes and number of chunks of each [ChunkType] read by [decode_with_stats()].
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    pub struct DecodeStats {
+        /// Number of pixels decoded.
+        pub pixels: usize,
+        /// Number of bytes consumed, including header and end marker but not any trailing whitespace.
+        pub bytes: usize,
+        pub run: usize,
+        pub index: usize,
+        pub luma: usize,
+        pub diff: usize,
+        pub rgb: usize,
+        pub rgba: usize,
+    }
 
-        let pixel: Pixel = Pixel::new(r, g, b, prev_pixel.a);
-        pixel
+    impl DecodeStats {
+        fn count(&mut self, tag: &ChunkType) {
+            let counter: &mut usize = match tag {
+                ChunkType::Run => &mut self.run,
+                ChunkType::Index => &mut self.index,
+                ChunkType::Luma => &mut self.luma,
+                ChunkType::Diff => &mut self.diff,
+                ChunkType::RGB => &mut self.rgb,
+                ChunkType::RGBA => &mut self.rgba,
+            };
+            *counter += 1;
+        }
     }
 
-    fn dec_luma(bytes: &[u8], prev_pixel: &Pixel) -> Pixel {
-        let dr: u8;
-        let dr_dg: u8;
-        let db_dg: u8;
-        let dg: u8;
-        let db: u8;
-
-        dg = bytes[0] & 0b00111111;
-        dr_dg = (bytes[1] & 0b11110000) >> 4;
-        db_dg = bytes[1] & 0b00001111;
-        dr = dr_dg + dg;
-        db = db_dg + dg;
+    /// Sizes and number of chunks of each [ChunkType] written by [encode_with_stats()].
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    pub struct EncodeStats {
+        /// Number of pixels encoded.
+        pub pixels: usize,
+        /// Size of the complete encoding in bytes, including header and end marker.
+        pub bytes: usize,
+        /// `bytes` divided by the size of the raw pixel data, `pixels * channels`; below 1.0 the encoding is smaller.
+        pub ratio: f64,
+        pub run: usize,
+        pub index: usize,
+        pub luma: usize,
+        pub diff: usize,
+        pub rgb: usize,
+        pub rgba: usize,
+    }
 
-        let r: u8 = prev_pixel.r.wrapping_add(dr);
-        let g: u8 = prev_pixel.g.wrapping_add(dg);
-        let b: u8 = prev_pixel.b.wrapping_add(db);
+    impl EncodeStats {
+        fn count(&mut self, tag: &ChunkType) {
+            let counter: &mut usize = match tag {
+                ChunkType::Run => &mut self.run,
+                ChunkType::Index => &mut self.index,
+                ChunkType::Luma => &mut self.luma,
+                ChunkType::Diff => &mut self.diff,
+                ChunkType::RGB => &mut self.rgb,
+                ChunkType::RGBA => &mut self.rgba,
+            };
+            *counter += 1;
+        }
+    }
 
-        let r: u8 = r.wrapping_sub(LUMA_BIAS_RB + LUMA_BIAS_G);
-        let g: u8 = g.wrapping_sub(LUMA_BIAS_G);
-        let b: u8 = b.wrapping_sub(LUMA_BIAS_RB + LUMA_BIAS_G);
+    /// Number of chunks of one type in a stream, the pixels they produce and the bytes they occupy.
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    pub struct ChunkTally {
+        pub chunks: usize,
+        pub pixels: usize,
+        pub bytes: usize,
+    }
+
+    /// Per-[ChunkType] tallies of a QOI stream, as returned by [analyze()].
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    pub struct ChunkHistogram {
+        pub run: ChunkTally,
+        pub index: ChunkTally,
+        pub luma: ChunkTally,
+        pub diff: ChunkTally,
+        pub rgb: ChunkTally,
+        pub rgba: ChunkTally,
+    }
+
+    impl ChunkHistogram {
+        fn tally_mut(&mut self, tag: &ChunkType) -> &mut ChunkTally {
+            match tag {
+                ChunkType::Run => &mut self.run,
+                ChunkType::Index => &mut self.index,
+                ChunkType::Luma => &mut self.luma,
+                ChunkType::Diff => &mut self.diff,
+                ChunkType::RGB => &mut self.rgb,
+                ChunkType::RGBA => &mut self.rgba,
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ChunkType {
+        Run,
+        Index,
+        Luma,
+        Diff,
+        RGB,
+        RGBA,
+    }
+
+    impl Pixel {
+        pub fn new(r: u8, g: u8, b: u8, a: u8) -> Pixel {
+            Pixel { r, g, b, a }
+        }
+        pub fn r(&self) -> u8 {
+            self.r
+        }
+
+        pub fn g(&self) -> u8 {
+            self.g
+        }
+
+        pub fn b(&self) -> u8 {
+            self.b
+        }
+
+        pub fn a(&self) -> u8 {
+            self.a
+        }
+
+        /// Returns the channel values ordered r, g, b, a.
+        pub fn channels(&self) -> (u8, u8, u8, u8) {
+            (self.r, self.g, self.b, self.a)
+        }
+
+        //channel values as an array, for indexing and iterating over them
+        fn to_array(self) -> [u8; 4] {
+            [self.r, self.g, self.b, self.a]
+        }
+
+        /// Parses a colour given as `RRGGBB` or `RRGGBBAA` hex digits, optionally preceded by `#`.
+        /// Alpha defaults to 255 for the 6-digit form. Returns [ImgError::DataError] for any other length or non-hex digits.
+        /// ```rust
+        /// # use qoi::qoi_lib::*;
+        /// assert_eq!(Pixel::from_hex("#ff8000"), Ok(Pixel::new(255, 128, 0, 255)));
+        /// assert_eq!(Pixel::from_hex("FF800080"), Ok(Pixel::new(255, 128, 0, 128)));
+        /// ```
+        pub fn from_hex(s: &str) -> Result<Pixel, ImgError> {
+            let digits: &str = s.strip_prefix('#').unwrap_or(s);
+            if !(digits.len() == 6 || digits.len() == 8)
+                || !digits.bytes().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(ImgError::DataError);
+            }
+            let value: u32 = u32::from_str_radix(digits, 16).map_err(|_| ImgError::DataError)?;
+            match digits.len() {
+                6 => Ok(Pixel::from_u32(value << 8 | 0xFF)),
+                _ => Ok(Pixel::from_u32(value)),
+            }
+        }
+
+        /// Formats the pixel as `#rrggbbaa` in lowercase hex, which [Pixel::from_hex] parses back.
+        pub fn to_hex(&self) -> String {
+            format!("#{:08x}", self.to_u32())
+        }
+
+        /// Packs the pixel into a `u32` as `0xRRGGBBAA`: red in the most significant byte, alpha in the least.
+        /// The layout does not depend on the platform's endianness; `to_u32().to_be_bytes()` yields `[r, g, b, a]`.
+        /// Same as [Pixel::to_u32_rgba].
+        pub fn to_u32(&self) -> u32 {
+            self.to_u32_rgba()
+        }
+
+        /// Unpacks a pixel from a `u32` laid out as `0xRRGGBBAA`, the inverse of [Pixel::to_u32].
+        pub fn from_u32(value: u32) -> Pixel {
+            Pixel::from_u32_rgba(value)
+        }
+
+        /// Packs the pixel into a `u32` as `0xRRGGBBAA`, i.e. red in the most significant byte.
+        pub fn to_u32_rgba(&self) -> u32 {
+            u32::from_be_bytes([self.r, self.g, self.b, self.a])
+        }
+
+        /// Unpacks a pixel from a `u32` laid out as `0xRRGGBBAA`, see [Pixel::to_u32_rgba].
+        pub fn from_u32_rgba(value: u32) -> Pixel {
+            let [r, g, b, a] = value.to_be_bytes();
+            Pixel::new(r, g, b, a)
+        }
+
+        /// Packs the pixel into a `u32` as `0xBBGGRRAA`, i.e. blue in the most significant byte.
+        pub fn to_u32_bgra(&self) -> u32 {
+            u32::from_be_bytes([self.b, self.g, self.r, self.a])
+        }
+
+        /// Unpacks a pixel from a `u32` laid out as `0xBBGGRRAA`, see [Pixel::to_u32_bgra].
+        pub fn from_u32_bgra(value: u32) -> Pixel {
+            let [b, g, r, a] = value.to_be_bytes();
+            Pixel::new(r, g, b, a)
+        }
+
+        /// Adds `other` channel by channel, clamping at 255. Alpha is added and clamped like the colour channels.
+        pub fn saturating_add(&self, other: &Pixel) -> Pixel {
+            Pixel::new(
+                self.r.saturating_add(other.r),
+                self.g.saturating_add(other.g),
+                self.b.saturating_add(other.b),
+                self.a.saturating_add(other.a),
+            )
+        }
+
+        /// Subtracts `other` channel by channel, clamping at 0. Alpha is subtracted and clamped like the colour channels.
+        pub fn saturating_sub(&self, other: &Pixel) -> Pixel {
+            Pixel::new(
+                self.r.saturating_sub(other.r),
+                self.g.saturating_sub(other.g),
+                self.b.saturating_sub(other.b),
+                self.a.saturating_sub(other.a),
+            )
+        }
+
+        fn channel_mut(&mut self, channel: Channel) -> &mut u8 {
+            match channel {
+                Channel::Red => &mut self.r,
+                Channel::Green => &mut self.g,
+                Channel::Blue => &mut self.b,
+                Channel::Alpha => &mut self.a,
+            }
+        }
+
+        //src-over compositing onto an opaque background, rounding to the nearest value
+        fn over(&self, bg: &Pixel) -> Pixel {
+            let a: u16 = self.a as u16;
+            let blend = |src: u8, dst: u8| -> u8 {
+                ((src as u16 * a + dst as u16 * (255 - a) + 127) / 255) as u8
+            };
+            Pixel::new(blend(self.r, bg.r), blend(self.g, bg.g), blend(self.b, bg.b), 255)
+        }
+
+        /// Returns the `palette` entry with the smallest squared Euclidean distance to this pixel across r, g, b
+        /// and a, the first one on ties, or `None` if the palette is empty.
+        pub fn nearest<'a>(&self, palette: &'a [Pixel]) -> Option<&'a Pixel> {
+            self.nearest_by(palette, 4)
+        }
+
+        /// Like [Pixel::nearest], but compares r, g and b only and ignores alpha.
+        pub fn nearest_rgb<'a>(&self, palette: &'a [Pixel]) -> Option<&'a Pixel> {
+            self.nearest_by(palette, 3)
+        }
+
+        //nearest palette entry by squared distance over the first `channels` channels
+        fn nearest_by<'a>(&self, palette: &'a [Pixel], channels: usize) -> Option<&'a Pixel> {
+            let distance = |p: &Pixel| -> u32 {
+                self.to_array()
+                    .into_iter()
+                    .zip(p.to_array())
+                    .take(channels)
+                    .map(|(a, b)| (a as i32 - b as i32).pow(2) as u32)
+                    .sum()
+            };
+            palette.iter().min_by_key(|p| distance(p))
+        }
+
+        fn equals(&self, other: &Pixel) -> bool {
+            (self.r == other.r) && (self.g == other.g) && (self.b == other.b) && (self.a == other.a)
+        }
+
+        fn equals_rgb(&self, other: &Pixel) -> bool {
+            (self.r == other.r) && (self.g == other.g) && (self.b == other.b)
+        }
+
+        //self = curr pixel, other = prev pixel
+        pub fn determine_chunk(
+            &self,
+            other: &Pixel,
+            buffer: &[Pixel],
+        ) -> (ChunkType, Option<(u8, u8, u8)>) {
+            self.determine_chunk_with(other, buffer, || self.diff(other))
+        }
+
+        //determine_chunk with the rgb deltas supplied by the caller, which the encoder may have batch-computed
+        fn determine_chunk_with(
+            &self,
+            other: &Pixel,
+            buffer: &[Pixel],
+            diff: impl FnOnce() -> (i16, i16, i16),
+        ) -> (ChunkType, Option<(u8, u8, u8)>) {
+            if self.equals(other) {
+                return (ChunkType::Run, None);
+            }
+
+            if self.equals(&buffer[color_hash(self) as usize]) {
+                return (ChunkType::Index, Some((color_hash(self), 0, 0)));
+            }
+
+            if self.a != other.a {
+                return (ChunkType::RGBA, None);
+            }
+
+            let diff_tuple: (i16, i16, i16) = diff();
+            let dr: i16 = diff_tuple.0;
+            let dg: i16 = diff_tuple.1;
+            let db: i16 = diff_tuple.2;
+
+            //the deltas wrap like the spec's signed chars, so e.g. 254 -> 1 is +3 and 1 -> 254 is -3
+            if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                let dr: u8 = (dr + DIFF_BIAS as i16) as u8;
+                let dg: u8 = (dg + DIFF_BIAS as i16) as u8;
+                let db: u8 = (db + DIFF_BIAS as i16) as u8;
+                (ChunkType::Diff, Some((dr, dg, db)))
+            } else if (-32..=31).contains(&dg)
+                && (-8..=7).contains(&(dr - dg))
+                && (-8..=7).contains(&(db - dg))
+            {
+                let dg_out: u8 = (dg + LUMA_BIAS_G as i16) as u8;
+                let dr_dg: u8 = (dr - dg + LUMA_BIAS_RB as i16) as u8;
+                let db_dg: u8 = (db - dg + LUMA_BIAS_RB as i16) as u8;
+                (ChunkType::Luma, Some((dg_out, dr_dg, db_dg)))
+            } else {
+                (ChunkType::RGB, None)
+            }
+        }
+        /// Wrapping per-channel difference `self - other` of the rgb channels as the spec computes it, i.e. the
+        /// difference modulo 256 read as a signed byte in `-128..=127`.
+        pub fn diff(&self, other: &Pixel) -> (i16, i16, i16) {
+            let dr: i16 = self.r.wrapping_sub(other.r) as i8 as i16;
+            let dg: i16 = self.g.wrapping_sub(other.g) as i8 as i16;
+            let db: i16 = self.b.wrapping_sub(other.b) as i8 as i16;
+
+            (dr, dg, db)
+        }
+    }
+
+    /// Per-channel saturating addition, including alpha, see [Pixel::saturating_add].
+    impl core::ops::Add for Pixel {
+        type Output = Pixel;
+
+        fn add(self, other: Pixel) -> Pixel {
+            self.saturating_add(&other)
+        }
+    }
+
+    /// Per-channel saturating subtraction, including alpha, see [Pixel::saturating_sub].
+    impl core::ops::Sub for Pixel {
+        type Output = Pixel;
+
+        fn sub(self, other: Pixel) -> Pixel {
+            self.saturating_sub(&other)
+        }
+    }
+
+    //Definition of header bytes
+    struct Header {
+        magic: [u8; 4], //magic bytes "qoif"
+        width: u32,     //image width in pixels (BE)
+        height: u32,    //image height in pixels (BE)
+        channels: u8,   // 3 = RGB, 4 = RBGA
+        colorspace: u8, // 0 = sRGB with linear alpha, 1 = all channels linear
+    }
+
+    impl Header {
+        fn convert_to_bytestream(&self) -> [u8; 14] {
+            let mut out: [u8; 14] = [0; 14];
+
+            //First, set magic bytes
+            out[0..4].copy_from_slice(&self.magic);
+
+            //split width and height into 8-bit chunks
+            let width_bytes = self.width.to_be_bytes();
+            let height_bytes = self.height.to_be_bytes();
+
+            out[4] = width_bytes[0];
+            out[5] = width_bytes[1];
+            out[6] = width_bytes[2];
+            out[7] = width_bytes[3];
+            out[8] = height_bytes[0];
+            out[9] = height_bytes[1];
+            out[10] = height_bytes[2];
+            out[11] = height_bytes[3];
+
+            //Set information bits
+            out[12] = self.channels;
+            out[13] = self.colorspace;
+
+            out
+        }
+    }
+
+    //Definition of End of Stream bytes
+    #[derive(Debug)]
+    struct End {
+        bytes: [u8; 8],
+    }
+    impl End {
+        fn new() -> End {
+            End {
+                bytes: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+            }
+        }
+    }
+
+    //chunks as defined in the QOI spec
+    const QOI_OP_RGB: u8 = 0b1111_1110;
+    const QOI_OP_RGBA: u8 = 0b1111_1111;
+    const QOI_OP_RUN: u8 = 0b1100_0000;
+    const QOI_OP_INDEX: u8 = 0b0000_0000;
+    const QOI_OP_DIFF: u8 = 0b0100_0000;
+    const QOI_OP_LUMA: u8 = 0b1000_0000;
+
+    //Biases as defined in the QOI spec
+    const RUN_BIAS: u8 = 1;
+
+    const DIFF_BIAS: u8 = 2;
+
+    const LUMA_BIAS_G: u8 = 32;
+    const LUMA_BIAS_RB: u8 = 8;
+
+    /// Number of whitespace bytes [decode] skips after the end marker, e.g. a `\r\n` added by text-mode transfers.
+    pub const MAX_TRAILING_WHITESPACE: usize = 4;
+
+    /// Number of bytes [encode_to_writer] collects before passing them on to its writer.
+    pub const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+    /// Number of pixels [encode_with_progress] encodes between two progress reports.
+    pub const PROGRESS_INTERVAL: u64 = 64 * 1024;
+
+    //3×3 kernels for Image::apply_kernel
+    /// Leaves the image unchanged, use with divisor 1.0.
+    pub const KERNEL_IDENTITY: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]];
+    /// Mean of the 3×3 neighbourhood, use with divisor 9.0.
+    pub const KERNEL_BOX_BLUR: [[f32; 3]; 3] = [[1.0, 1.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 1.0]];
+    /// Approximated gaussian blur, use with divisor 16.0.
+    pub const KERNEL_GAUSSIAN_BLUR: [[f32; 3]; 3] =
+        [[1.0, 2.0, 1.0], [2.0, 4.0, 2.0], [1.0, 2.0, 1.0]];
+    /// Use with divisor 1.0.
+    pub const KERNEL_SHARPEN: [[f32; 3]; 3] =
+        [[0.0, -1.0, 0.0], [-1.0, 5.0, -1.0], [0.0, -1.0, 0.0]];
+    /// Use with divisor 1.0.
+    pub const KERNEL_EDGE_DETECT: [[f32; 3]; 3] =
+        [[-1.0, -1.0, -1.0], [-1.0, 8.0, -1.0], [-1.0, -1.0, -1.0]];
+    /// Use with divisor 1.0, optionally with a bias of 128.0.
+    pub const KERNEL_EMBOSS: [[f32; 3]; 3] = [[-2.0, -1.0, 0.0], [-1.0, 1.0, 1.0], [0.0, 1.0, 2.0]];
+
+    //hash function for assigning buffer indices to stored pixels
+    fn color_hash(pixel: &Pixel) -> u8 {
+        let store: u32 =
+            pixel.r as u32 * 3 + pixel.g as u32 * 5 + pixel.b as u32 * 7 + pixel.a as u32 * 11;
+        (store % 64) as u8
+    }
+
+    pub fn encode_from_image(img: &Image) -> Vec<u8> {
+        encode_with_stats(img).0
+    }
+
+    /// Same as [encode_from_image()], but writes a stream with `channels` channels regardless of `img.channels`.
+    /// Encoding as 3 channels ignores alpha entirely, so no `QOI_OP_RGBA` chunks are written; encoding a 3-channel
+    /// image as 4 channels stores an alpha of 255 for every pixel.
+    /// Returns [ImgError::InvalidFormat] if `channels` is neither 3 nor 4.
+    pub fn encode_as(img: &Image, channels: u8) -> Result<Vec<u8>, ImgError> {
+        check_format(channels, img.colorspace)?;
+        if channels == img.channels {
+            return Ok(encode_from_image(img));
+        }
+        let pixels: Vec<Pixel> = match channels {
+            3 => img.pixels.clone(),
+            _ => img
+                .pixels
+                .iter()
+                .map(|pixel| Pixel { a: 255, ..*pixel })
+                .collect(),
+        };
+        Ok(encode_from_image(&Image {
+            pixels,
+            channels,
+            ..*img
+        }))
+    }
+
+    /// Encodes `w * h` RGBA pixels, 4 bytes each, into an sRGB QOI stream without logging anything.
+    /// Meant for in-memory use, e.g. from WebAssembly, together with [decode_to_rgba()].
+    ///
+    /// # Panics
+    /// Panics if `pixels` does not hold exactly `w * h * 4` bytes.
+    pub fn encode_rgba(pixels: &[u8], w: u32, h: u32) -> Vec<u8> {
+        let img: Image = match Image::new(pixels.to_vec(), w, h, 4, 0) {
+            Ok(img) => img,
+            Err(_) => panic!(
+                "encode_rgba expects {}x{}x4 bytes, got {}",
+                w,
+                h,
+                pixels.len()
+            ),
+        };
+        let mut encoded_bytes: Vec<u8> = Vec::new();
+        //writing into a Vec<u8> cannot fail
+        encode_chunks(
+            &img,
+            |block| extend(&mut encoded_bytes, block),
+            |chunk, n| Ok(()),
+        )
+        .unwrap();
+        encoded_bytes
+    }
+
+    /// Encodes every pixel of `img` as a `QOI_OP_RGBA` chunk, without runs, index hits or differences.
+    /// The result is a valid QOI stream of exactly `14 + 5 * pixels + 8` bytes which decodes to the same image as
+    /// [encode_from_image()], useful as a baseline and for generating test vectors.
+    /// 3-channel images are written with an alpha of 255.
+    pub fn encode_raw(img: &Image) -> Vec<u8> {
+        let head = Header {
+            magic: *b"qoif",
+            width: img.width,
+            height: img.height,
+            channels: img.channels,
+            colorspace: img.colorspace,
+        };
+        let mut encoded_bytes: Vec<u8> = Vec::with_capacity(14 + 5 * img.pixels.len() + 8);
+        encoded_bytes.extend_from_slice(&head.convert_to_bytestream());
+        for pixel in &img.pixels {
+            let alpha: u8 = if img.channels == 3 { 255 } else { pixel.a };
+            encoded_bytes.extend_from_slice(&[QOI_OP_RGBA, pixel.r, pixel.g, pixel.b, alpha]);
+        }
+        encoded_bytes.extend_from_slice(&End::new().bytes);
+        encoded_bytes
+    }
+
+    /// Same as [encode_from_image()], but additionally reports sizes and how many chunks of each type were written.
+    pub fn encode_with_stats(img: &Image) -> (Vec<u8>, EncodeStats) {
+        let mut encoded_bytes: Vec<u8> = Vec::new();
+        let mut stats: EncodeStats = EncodeStats::default();
+        //writing into a Vec<u8> cannot fail
+        encode_chunks(
+            img,
+            |block| extend(&mut encoded_bytes, block),
+            |chunk, n| {
+                stats.count(chunk);
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        stats.pixels = img.pixels.len();
+        stats.bytes = encoded_bytes.len();
+        stats.ratio = stats.bytes as f64 / (stats.pixels * img.channels as usize).max(1) as f64;
+        info!("Number of pixels processed: {}.", stats.pixels);
+        info!("Number of bytes in encoding: {:?}.", stats.bytes - 22);
+        info!(
+            "Compression rate: {:.2}%.",
+            (1.0 - (stats.bytes - 22) as f64 / (stats.pixels * 4) as f64) * 100.0
+        );
+
+        (encoded_bytes, stats)
+    }
+
+    /// Same as [encode_from_image()], but calls `cb(pixels_done, total_pixels)` about every [PROGRESS_INTERVAL] pixels.
+    /// The final call always reports `pixels_done == total_pixels`, also for images without pixels.
+    pub fn encode_with_progress(img: &Image, mut cb: impl FnMut(u64, u64)) -> Vec<u8> {
+        let total: u64 = img.pixels.len() as u64;
+        let mut done: u64 = 0;
+        let mut reported: u64 = 0;
+        let mut encoded_bytes: Vec<u8> = Vec::new();
+        //writing into a Vec<u8> cannot fail
+        encode_chunks(
+            img,
+            |block| extend(&mut encoded_bytes, block),
+            |chunk, n| {
+                done += n;
+                if done - reported >= PROGRESS_INTERVAL && done < total {
+                    cb(done, total);
+                    reported = done;
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+        cb(total, total);
+        encoded_bytes
+    }
+
+    /// Same as [encode_from_image()], but checks `should_cancel` once per row of pixels
+    /// and returns `None` as soon as it is set, discarding the partial encoding.
+    pub fn encode_cancellable(img: &Image, should_cancel: &AtomicBool) -> Option<Vec<u8>> {
+        let row: u64 = img.width.max(1) as u64;
+        let mut done: u64 = 0;
+        let mut next_check: u64 = 0;
+        let mut encoded_bytes: Vec<u8> = Vec::new();
+        let encoded = encode_chunks(
+            img,
+            |block| extend(&mut encoded_bytes, block),
+            |chunk, n| {
+                if done >= next_check {
+                    if should_cancel.load(Ordering::Relaxed) {
+                        return Err(());
+                    }
+                    next_check += row;
+                }
+                done += n;
+                Ok(())
+            },
+        );
+        //writing into a Vec<u8> cannot fail, so any error is the cancellation
+        encoded.ok().map(|()| encoded_bytes)
+    }
+
+    /// Encodes `img` straight into `writer`, emitting chunks as they are produced instead of collecting the whole encoding in memory.
+    /// Chunks are buffered in blocks of [STREAM_BUFFER_SIZE] bytes before being handed to the writer.
+    /// ```rust
+    /// # use qoi::qoi_lib::*;
+    /// # fn main() -> std::io::Result<()> {
+    /// let img: Image = Image::from_pixels(vec![Pixel::new(0, 0, 0, 255); 4], 2, 2, 4, 0).unwrap();
+    /// let mut out: Vec<u8> = Vec::new();
+    /// encode_to_writer(&img, &mut out)?;
+    /// assert_eq!(&out[0..4], b"qoif");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn encode_to_writer<W: Write>(img: &Image, writer: &mut W) -> std::io::Result<()> {
+        encode_chunks(img, |block| writer.write_all(block), |chunk, n| Ok(()))?;
+        writer.flush()
+    }
+
+    //write_block for encode_chunks collecting the encoding in memory
+    fn extend(encoded_bytes: &mut Vec<u8>, block: &[u8]) -> Result<(), ()> {
+        encoded_bytes.extend_from_slice(block);
+        Ok(())
+    }
+
+    //Encodes `img` in blocks of about STREAM_BUFFER_SIZE bytes handed to `write_block`, calling `on_chunk` with every
+    //chunk written and the number of pixels it covers. An error returned by either closure aborts encoding.
+    fn encode_chunks<E>(
+        img: &Image,
+        mut write_block: impl FnMut(&[u8]) -> Result<(), E>,
+        mut on_chunk: impl FnMut(&ChunkType, u64) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut encoded_bytes: Vec<u8> = Vec::with_capacity(STREAM_BUFFER_SIZE + 8);
+
+        let head = Header {
+            magic: *b"qoif",
+            width: img.width,
+            height: img.height,
+            channels: img.channels,
+            colorspace: img.colorspace,
+        };
+        let head_stream = head.convert_to_bytestream();
+
+        for i in head_stream {
+            encoded_bytes.push(i);
+        }
+
+        for_each_chunk(img, |chunk, data, pixel, run| -> Result<(), E> {
+            if encoded_bytes.len() >= STREAM_BUFFER_SIZE {
+                write_block(&encoded_bytes)?;
+                encoded_bytes.clear();
+            }
+            let n_pixels: u64 = if chunk == ChunkType::Run {
+                run as u64
+            } else {
+                1
+            };
+            on_chunk(&chunk, n_pixels)?;
+            push_chunk(&mut encoded_bytes, chunk, data, pixel, run);
+            Ok(())
+        })?;
+
+        let end_bytes = End::new();
+        for i in end_bytes.bytes {
+            encoded_bytes.push(i)
+        }
+
+        write_block(&encoded_bytes)
+    }
+
+    //Runs the encoder's chunk selection over `img` and hands every chunk to `visit` in stream order.
+    //`visit` receives the chunk type, the payload from determine_chunk, the encoded pixel and, for runs, the run length.
+    fn for_each_chunk<E>(
+        img: &Image,
+        visit: impl FnMut(ChunkType, Option<(u8, u8, u8)>, &Pixel, u8) -> Result<(), E>,
+    ) -> Result<(), E> {
+        walk_chunks(img, cfg!(feature = "simd"), visit)
+    }
+
+    //for_each_chunk, with `batched` choosing between the simd deltas and the scalar Pixel::diff.
+    //Without the `simd` feature `batched` has no effect.
+    fn walk_chunks<E>(
+        img: &Image,
+        batched: bool,
+        mut visit: impl FnMut(ChunkType, Option<(u8, u8, u8)>, &Pixel, u8) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut prev_pixel: Pixel = Pixel::new(0, 0, 0, 255);
+        let mut prev_buffer: [Pixel; 64] = [Pixel::new(0, 0, 0, 0); 64];
+        let mut run: u64 = 0;
+
+        //3-channel images carry no alpha, so alpha is treated as constant 255 and RGBA chunks never occur
+        let opaque: bool = img.channels == 3;
+        let pixels = img.pixels.iter().map(|pixel| match opaque {
+            true => Pixel { a: 255, ..*pixel },
+            false => *pixel,
+        });
+
+        #[cfg(feature = "simd")]
+        let mut diffs: [(i16, i16, i16); simd::BATCH] = [(0, 0, 0); simd::BATCH];
+
+        //a trailing None flushes the final run
+        for (i, pixel) in pixels.map(Some).chain(core::iter::once(None)).enumerate() {
+            #[cfg(feature = "simd")]
+            if batched && i % simd::BATCH == 0 && i < img.pixels.len() {
+                let end: usize = (i + simd::BATCH).min(img.pixels.len());
+                let first_prev: Pixel = match i {
+                    0 => Pixel::new(0, 0, 0, 255),
+                    _ => img.pixels[i - 1],
+                };
+                simd::batch_diff(&img.pixels[i..end], first_prev, &mut diffs);
+            }
+            let diff = |pixel: &Pixel| {
+                #[cfg(feature = "simd")]
+                if batched {
+                    return diffs[i % simd::BATCH];
+                }
+                pixel.diff(&prev_pixel)
+            };
+            let chunk = pixel.map(|pixel| {
+                pixel.determine_chunk_with(&prev_pixel, &prev_buffer, || diff(&pixel))
+            });
+            //every processed pixel enters the index, as in the decoder. For runs and index hits this only matters
+            //for a run of the initial previous pixel at the start, which the encoder has not seen before.
+            if let Some((ChunkType::Run, None)) = chunk {
+                prev_buffer[color_hash(&prev_pixel) as usize] = prev_pixel;
+                run += 1;
+                continue;
+            }
+
+            //a single run chunk covers at most 62 pixels
+            while run > 0 {
+                let take: u64 = run.min(62);
+                visit(ChunkType::Run, None, &prev_pixel, take as u8)?;
+                run -= take;
+            }
+
+            let (Some(pixel), Some((chunk, data))) = (pixel, chunk) else {
+                break;
+            };
+            prev_buffer[color_hash(&pixel) as usize] = pixel;
+            prev_pixel = pixel;
+            visit(chunk, data, &pixel, 0)?;
+        }
+        Ok(())
+    }
+
+    //Batched rgb deltas for the encoder, bit-identical to Pixel::diff against the preceding pixel
+    #[cfg(feature = "simd")]
+    mod simd {
+        use super::Pixel;
+        use wide::{i16x16, i8x16, u8x16};
+
+        pub(super) const BATCH: usize = 64;
+
+        //Writes pixels[k].diff(prev) into out[k], where prev is `first_prev` for k = 0 and pixels[k - 1] otherwise.
+        //Four pixels are handled per 16-lane vector; `pixels` may be shorter than BATCH.
+        pub(super) fn batch_diff(
+            pixels: &[Pixel],
+            first_prev: Pixel,
+            out: &mut [(i16, i16, i16); BATCH],
+        ) {
+            let mut prev: Pixel = first_prev;
+            for (quad, out) in pixels.chunks(4).zip(out.chunks_mut(4)) {
+                let mut curr_bytes: [u8; 16] = [0; 16];
+                let mut prev_bytes: [u8; 16] = [0; 16];
+                for (k, pixel) in quad.iter().enumerate() {
+                    curr_bytes[4 * k..4 * k + 4].copy_from_slice(&pixel.to_array());
+                    prev_bytes[4 * k..4 * k + 4].copy_from_slice(&prev.to_array());
+                    prev = *pixel;
+                }
+                let delta: u8x16 = u8x16::new(curr_bytes) - u8x16::new(prev_bytes);
+                let delta: [i16; 16] =
+                    i16x16::from_i8x16(wide::bytemuck::cast::<u8x16, i8x16>(delta)).to_array();
+                for (k, out) in out.iter_mut().take(quad.len()).enumerate() {
+                    *out = (delta[4 * k], delta[4 * k + 1], delta[4 * k + 2]);
+                }
+            }
+        }
+    }
+
+    //Appends the bytes of a single chunk as produced by for_each_chunk
+    fn push_chunk(
+        encoded_bytes: &mut Vec<u8>,
+        chunk: ChunkType,
+        data: Option<(u8, u8, u8)>,
+        pixel: &Pixel,
+        run: u8,
+    ) {
+        match (chunk, data) {
+            (ChunkType::Run, None) => {
+                encoded_bytes.push(QOI_OP_RUN | (run - RUN_BIAS));
+            }
+            (ChunkType::Index, Some((index, irr1, irr2))) => {
+                encoded_bytes.push(QOI_OP_INDEX | index);
+            }
+            (ChunkType::Diff, Some((dr, dg, db))) => {
+                let mut out: u8 = 0b0000_0000;
+                out |= db;
+                out |= dg << 2;
+                out |= dr << 4;
+                encoded_bytes.push(QOI_OP_DIFF | out);
+            }
+            (ChunkType::Luma, Some((dg, dr_dg, db_dg))) => {
+                let mut out: [u8; 2] = [0b0000_0000; 2];
+                out[0] |= dg;
+                out[0] |= QOI_OP_LUMA;
+                out[1] |= db_dg;
+                out[1] |= dr_dg << 4;
+                encoded_bytes.push(out[0]);
+                encoded_bytes.push(out[1]);
+            }
+            (ChunkType::RGB, None) => {
+                encoded_bytes.push(QOI_OP_RGB);
+                encoded_bytes.push(pixel.r);
+                encoded_bytes.push(pixel.g);
+                encoded_bytes.push(pixel.b);
+            }
+            (ChunkType::RGBA, None) => {
+                encoded_bytes.push(QOI_OP_RGBA);
+                encoded_bytes.push(pixel.r);
+                encoded_bytes.push(pixel.g);
+                encoded_bytes.push(pixel.b);
+                encoded_bytes.push(pixel.a);
+            }
+            _ => {
+                panic!("Critical error at encoding stage: Illegal output from difference function.")
+            }
+        }
+    }
+
+    /// Runs the encoder on `img` without producing any output and returns the exact length of the encoding,
+    /// including header and end marker. Always equal to `encode_from_image(img).len()`.
+    pub fn exact_encoded_len(img: &Image) -> usize {
+        let mut len: usize = 14 + 8;
+        let _ = for_each_chunk(img, |chunk, data, pixel, run| -> Result<(), ()> {
+            len += chunk_len(&chunk);
+            Ok(())
+        });
+        len
+    }
+
+    /// Simulates the encoder on `img` and reports, for each of the 64 index slots, how many distinct colours were stored in it.
+    /// Slots with a count above one are shared by colliding colours, which lowers the number of index hits.
+    pub fn index_slot_pressure(img: &Image) -> [u32; 64] {
+        let mut slot_colors: [BTreeSet<u32>; 64] = array_init::array_init(|_| BTreeSet::new());
+
+        let _ = for_each_chunk(img, |chunk, data, pixel, run| -> Result<(), ()> {
+            if chunk != ChunkType::Run && chunk != ChunkType::Index {
+                slot_colors[color_hash(pixel) as usize].insert(pixel.to_u32_rgba());
+            }
+            Ok(())
+        });
+
+        array_init::array_init(|i| slot_colors[i].len() as u32)
+    }
+    /// Writes the encoded `bytes` to the file at `path`, exactly as given.
+    /// Use [with_qoi_extension()] to add the `.qoi` extension to paths without one.
+    /// ```rust
+    /// # use qoi::qoi_lib::*;
+    /// # fn main() {
+    /// 
+    /// let bytes: Vec<u8> = vec![];
+    /// let path = std::env::temp_dir().join(with_qoi_extension("qoi-image"));
+    /// write_to_file(bytes, path);
+    /// #
+    /// # 
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_to_file<P: AsRef<Path>>(bytes: Vec<u8>, path: P) -> std::io::Result<()> {
+        File::create(path)?.write_all(&bytes)
+    }
+
+    /// Same as [write_to_file()], but fails with [std::io::ErrorKind::AlreadyExists] instead of overwriting an existing file.
+    #[cfg(feature = "std")]
+    pub fn write_to_file_no_clobber<P: AsRef<Path>>(bytes: Vec<u8>, path: P) -> std::io::Result<()> {
+        File::create_new(path)?.write_all(&bytes)
+    }
+
+    /// Appends `.qoi` to `path` if it has no extension, so `out` becomes `out.qoi` while `out.qoi` and `out.bin`
+    /// are returned unchanged.
+    #[cfg(feature = "std")]
+    pub fn with_qoi_extension<P: AsRef<Path>>(path: P) -> PathBuf {
+        let path: &Path = path.as_ref();
+        if path.extension().is_some() {
+            return path.to_path_buf();
+        }
+        let mut with_extension: OsString = path.as_os_str().to_owned();
+        with_extension.push(".qoi");
+        PathBuf::from(with_extension)
+    }
+
+    /// Image metadata as stored in the header of a `.qoi` file.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct QoiHeader {
+        pub width: u32,
+        pub height: u32,
+        pub channels: u8,
+        pub colorspace: u8,
+    }
+
+    /// Reads only the 14-byte header at the start of `bytes`, without decoding any pixels.
+    /// Returns [ImgError::HeaderError] if `bytes` is too short or does not start with the `qoif` magic bytes.
+    pub fn decode_header(bytes: &[u8]) -> Result<QoiHeader, ImgError> {
+        if bytes.len() < 14 {
+            return Err(ImgError::HeaderError);
+        }
+        let (width, height, channels, colorspace) = read_header(&bytes[0..14])?;
+        Ok(QoiHeader {
+            width,
+            height,
+            channels,
+            colorspace,
+        })
+    }
+
+    fn read_header(bytes: &[u8]) -> Result<(u32, u32, u8, u8), ImgError> {
+        if bytes[0..4] == *b"qoif" {
+            let mut width: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0000;
+            let mut height: u32 = 0b0000_0000_0000_0000_0000_0000_0000_0000;
+            width |= (bytes[4] as u32) << 24;
+            width |= (bytes[5] as u32) << 16;
+            width |= (bytes[6] as u32) << 8;
+            width |= bytes[7] as u32;
+            height |= (bytes[8] as u32) << 24;
+            height |= (bytes[9] as u32) << 16;
+            height |= (bytes[10] as u32) << 8;
+            height |= bytes[11] as u32;
+            Ok((width, height, bytes[12], bytes[13]))
+        } else {
+            Err(ImgError::HeaderError)
+        }
+    }
+
+    fn read_tag(tag: u8) -> Result<ChunkType, ImgError> {
+        if tag == QOI_OP_RGB {
+            return Ok(ChunkType::RGB);
+        }
+        if tag == QOI_OP_RGBA {
+            return Ok(ChunkType::RGBA);
+        }
+        if (tag & 0b1100_0000) == QOI_OP_DIFF {
+            return Ok(ChunkType::Diff);
+        }
+        if (tag & 0b1100_0000) == QOI_OP_INDEX {
+            return Ok(ChunkType::Index);
+        }
+        if (tag & 0b1100_0000) == QOI_OP_LUMA {
+            return Ok(ChunkType::Luma);
+        }
+        if (tag & 0b1100_0000) == QOI_OP_RUN {
+            return Ok(ChunkType::Run);
+        }
+        Err(ImgError::DecodeError)
+    }
+
+    fn dec_rgb(bytes: &[u8], alpha: u8) -> Pixel {
+        Pixel::new(bytes[1], bytes[2], bytes[3], alpha)
+    }
+
+    fn dec_rgba(bytes: &[u8]) -> Pixel {
+        Pixel::new(bytes[1], bytes[2], bytes[3], bytes[4])
+    }
+
+    fn dec_diff(byte: u8, prev_pixel: &Pixel) -> Pixel {
+        let dr: u8 = (byte & 0b00110000) >> 4;
+        let dg: u8 = (byte & 0b00001100) >> 2;
+        let db: u8 = byte & 0b00000011;
+
+        let r: u8 = prev_pixel.r.wrapping_add(dr);
+        let g: u8 = prev_pixel.g.wrapping_add(dg);
+        let b: u8 = prev_pixel.b.wrapping_add(db);
+
+        let r: u8 = r.wrapping_sub(DIFF_BIAS);
+        let b: u8 = b.wrapping_sub(DIFF_BIAS);
+        let g: u8 = g.wrapping_sub(DIFF_BIAS);
+
+        Pixel::new(r, g, b, prev_pixel.a)
+    }
+
+    fn dec_luma(bytes: &[u8], prev_pixel: &Pixel) -> Pixel {
+        let dg: u8 = bytes[0] & 0b00111111;
+        let dr_dg: u8 = (bytes[1] & 0b11110000) >> 4;
+        let db_dg: u8 = bytes[1] & 0b00001111;
+        let dr: u8 = dr_dg + dg;
+        let db: u8 = db_dg + dg;
+
+        let r: u8 = prev_pixel.r.wrapping_add(dr);
+        let g: u8 = prev_pixel.g.wrapping_add(dg);
+        let b: u8 = prev_pixel.b.wrapping_add(db);
+
+        let r: u8 = r.wrapping_sub(LUMA_BIAS_RB + LUMA_BIAS_G);
+        let g: u8 = g.wrapping_sub(LUMA_BIAS_G);
+        let b: u8 = b.wrapping_sub(LUMA_BIAS_RB + LUMA_BIAS_G);
+
+        Pixel::new(r, g, b, prev_pixel.a)
+    }
+
+    fn dec_run() {}
+
+    //Number of bytes occupied by a chunk of the given type, including its tag
+    fn chunk_len(tag: &ChunkType) -> usize {
+        match tag {
+            ChunkType::RGB => 4,
+            ChunkType::RGBA => 5,
+            ChunkType::Luma => 2,
+            ChunkType::Diff | ChunkType::Index | ChunkType::Run => 1,
+        }
+    }
+
+    //Decoder state carried from chunk to chunk: the previous pixel and the index of seen pixels
+    struct DecoderState {
+        prev_pixel: Pixel,
+        prev_buffer: [Pixel; 64],
+    }
+
+    impl DecoderState {
+        fn new() -> DecoderState {
+            DecoderState {
+                prev_pixel: Pixel::new(0, 0, 0, 255),
+                prev_buffer: [Pixel::new(0, 0, 0, 0); 64],
+            }
+        }
+
+        //Decodes a single chunk, `bytes` holding exactly the chunk_len(tag) bytes of the chunk.
+        //Returns the decoded pixel and how many times it is repeated.
+        fn decode_chunk(&mut self, tag: &ChunkType, bytes: &[u8]) -> (Pixel, u8) {
+            let (dec_pix, count): (Pixel, u8) = match tag {
+                ChunkType::RGB => (dec_rgb(bytes, self.prev_pixel.a), 1),
+                ChunkType::RGBA => (dec_rgba(bytes), 1),
+                ChunkType::Diff => (dec_diff(bytes[0], &self.prev_pixel), 1),
+                ChunkType::Index => (self.prev_buffer[(bytes[0] & 0b00111111) as usize], 1),
+                ChunkType::Luma => (dec_luma(bytes, &self.prev_pixel), 1),
+                ChunkType::Run => (self.prev_pixel, (bytes[0] & 0b00111111) + RUN_BIAS),
+            };
+            self.prev_pixel = dec_pix;
+            self.prev_buffer[color_hash(&dec_pix) as usize] = dec_pix;
+            (dec_pix, count)
+        }
+    }
+
+    /// Decodes a QOI stream into an [Image].
+    /// Inputs shorter than a header and end marker (22 bytes) or without the `qoif` magic bytes result in an
+    /// [ImgError::HeaderError], malformed chunks in an [ImgError::DecodeError].
+    pub fn decode(bytes: Vec<u8>) -> Result<Image, ImgError> {
+        decode_slice(&bytes)
+    }
+
+    /// Same as [decode()], but borrows the encoded bytes, e.g. from a memory-mapped file, instead of taking ownership.
+    pub fn decode_slice(bytes: &[u8]) -> Result<Image, ImgError> {
+        decode_slice_with_stats(bytes).map(|(img, stats)| img)
+    }
+
+    /// Decodes a QOI stream into RGBA bytes, 4 per pixel regardless of the stream's channels, and returns them with the
+    /// width and height. The counterpart of [encode_rgba()].
+    pub fn decode_to_rgba(bytes: &[u8]) -> Result<(Vec<u8>, u32, u32), ImgError> {
+        let img: Image = decode_slice(bytes)?;
+        Ok((img.to_bytes_rgba(), img.width, img.height))
+    }
+
+    /// Same as [decode_slice()], but additionally reports the number of pixels decoded, the number of bytes consumed
+    /// and how many chunks of each type were read.
+    pub fn decode_with_stats(bytes: &[u8]) -> Result<(Image, DecodeStats), ImgError> {
+        let (img, stats) = decode_slice_with_stats(bytes)?;
+        info!("Number of pixels decoded: {}.", stats.pixels);
+        info!("Number of bytes consumed: {}.", stats.bytes);
+        Ok((img, stats))
+    }
+
+    /// Same as [decode_slice()], but checks `should_cancel` once per row of pixels
+    /// and returns `Ok(None)` as soon as it is set.
+    pub fn decode_cancellable(
+        bytes: &[u8],
+        should_cancel: &AtomicBool,
+    ) -> Result<Option<Image>, ImgError> {
+        decode_core(bytes, should_cancel).map(|decoded| decoded.map(|(img, stats)| img))
+    }
+
+    //Shared implementation of decode_slice and decode_with_stats, without logging
+    fn decode_slice_with_stats(bytes: &[u8]) -> Result<(Image, DecodeStats), ImgError> {
+        decode_core(bytes, &AtomicBool::new(false))
+            .map(|decoded| decoded.expect("decoding cannot be cancelled without a request"))
+    }
+
+    //Decodes a QOI stream, returning None if `should_cancel` is found to be set
+    fn decode_core(
+        bytes: &[u8],
+        should_cancel: &AtomicBool,
+    ) -> Result<Option<(Image, DecodeStats)>, ImgError> {
+        //too short for a header and end marker, most likely not a QOI file at all
+        if bytes.len() < 14 + 8 {
+            debug!(
+                "Input of {} bytes is too short for a QOI file.",
+                bytes.len()
+            );
+            return Err(ImgError::HeaderError);
+        }
+        let mut state: DecoderState = DecoderState::new();
+        let mut stats: DecodeStats = DecodeStats::default();
+
+        let header: QoiHeader = decode_header(bytes)?;
+        let (width, height, channels, colorspace) = (
+            header.width,
+            header.height,
+            header.channels,
+            header.colorspace,
+        );
+        let n_pixels: usize = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or(ImgError::DecodeError)?;
+        //a single chunk yields at most 62 pixels, so a crafted header cannot force a huge allocation
+        let mut pixels: Vec<Pixel> = Vec::with_capacity(n_pixels.min(bytes.len() * 62));
+
+        let body: &[u8] = strip_end(bytes)?;
+
+        let mut i: usize = 14;
+        let mut next_check: usize = 0;
+
+        while i < body.len() {
+            if pixels.len() >= next_check {
+                if should_cancel.load(Ordering::Relaxed) {
+                    return Ok(None);
+                }
+                next_check += width.max(1) as usize;
+            }
+            let tag: ChunkType = read_tag(body[i])?;
+            let len: usize = chunk_len(&tag);
+            if body.len() - i < len {
+                debug!("Chunk at byte {} truncated: {} bytes needed.", i, len);
+                return Err(ImgError::DecodeError);
+            }
+            let (dec_pix, count) = state.decode_chunk(&tag, &body[i..i + len]);
+            stats.count(&tag);
+            for j in 0..count {
+                pixels.push(dec_pix);
+            }
+            i += len;
+        }
+
+        if pixels.len() != n_pixels {
+            return Err(ImgError::PixelCountMismatch {
+                expected: n_pixels,
+                actual: pixels.len(),
+            });
+        }
+
+        stats.pixels = pixels.len();
+        stats.bytes = body.len() + 8;
+        let img = Image::from_pixels(pixels, width, height, channels, colorspace)?;
+        Ok(Some((img, stats)))
+    }
+
+    /// Decodes a QOI stream straight into `out` as RGBA bytes, row by row, and returns `(width, height)`.
+    /// `out` must hold at least `width * height * 4` bytes, which can be determined beforehand with [decode_header()];
+    /// a smaller buffer results in an [ImgError::DataError]. Bytes past the image are left untouched.
+    /// Malformed streams result in an [ImgError::DecodeError], in which case `out` may be partially written.
+    pub fn decode_into(bytes: &[u8], out: &mut [u8]) -> Result<(u32, u32), ImgError> {
+        let header: QoiHeader = decode_header(bytes)?;
+        let n_bytes: usize = (header.width as usize)
+            .checked_mul(header.height as usize)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or(ImgError::DecodeError)?;
+        if out.len() < n_bytes {
+            debug!(
+                "Output buffer holds {} bytes, {} needed.",
+                out.len(),
+                n_bytes
+            );
+            return Err(ImgError::DataError);
+        }
+
+        let body: &[u8] = strip_end(bytes)?;
+        let mut state: DecoderState = DecoderState::new();
+        let mut pos: usize = 0;
+        let mut i: usize = 14;
+
+        while i < body.len() {
+            let tag: ChunkType = read_tag(body[i])?;
+            let len: usize = chunk_len(&tag);
+            if body.len() - i < len {
+                return Err(ImgError::DecodeError);
+            }
+            let (dec_pix, count) = state.decode_chunk(&tag, &body[i..i + len]);
+            for j in 0..count {
+                if pos == n_bytes {
+                    return Err(ImgError::DecodeError);
+                }
+                out[pos..pos + 4].copy_from_slice(&dec_pix.to_array());
+                pos += 4;
+            }
+            i += len;
+        }
+
+        if pos != n_bytes {
+            return Err(ImgError::PixelCountMismatch {
+                expected: n_bytes / 4,
+                actual: pos / 4,
+            });
+        }
+        Ok((header.width, header.height))
+    }
+
+    //Returns `bytes` without the end marker, tolerating a few whitespace bytes appended by text-mode transfers
+    fn strip_end(bytes: &[u8]) -> Result<&[u8], ImgError> {
+        let mut end: usize = bytes.len();
+        while bytes.len() - end < MAX_TRAILING_WHITESPACE
+            && end > 0
+            && bytes[end - 1].is_ascii_whitespace()
+        {
+            end -= 1;
+        }
+        if end < 14 + 8 || bytes[end - 8..end] != End::new().bytes {
+            debug!("Ending bytes not present.");
+            return Err(ImgError::DecodeError);
+        }
+        Ok(&bytes[..end - 8])
+    }
+
+    /// Checks that `bytes` is a structurally valid QOI stream without decoding any pixels:
+    /// valid header, complete chunks, a pixel count matching the header and the end marker.
+    /// Returns the [ImgError] describing the first problem encountered.
+    pub fn verify(bytes: &[u8]) -> Result<(), ImgError> {
+        analyze(bytes).map(|histogram| ())
+    }
+
+    /// Walks the chunks of a QOI stream without decoding any pixels and tallies, per chunk type,
+    /// how many chunks occur, how many pixels they produce and how many bytes they take up.
+    /// Fails under the same conditions as [verify()].
+    pub fn analyze(bytes: &[u8]) -> Result<ChunkHistogram, ImgError> {
+        let header: QoiHeader = decode_header(bytes)?;
+        let n_pixels: usize = (header.width as usize)
+            .checked_mul(header.height as usize)
+            .ok_or(ImgError::DecodeError)?;
+        let body: &[u8] = strip_end(bytes)?;
+
+        let mut histogram: ChunkHistogram = ChunkHistogram::default();
+        let mut count: usize = 0;
+        let mut i: usize = 14;
+        while i < body.len() {
+            let tag: ChunkType = read_tag(body[i])?;
+            let len: usize = chunk_len(&tag);
+            if body.len() - i < len {
+                return Err(ImgError::DecodeError);
+            }
+            let pixels: usize = match tag {
+                ChunkType::Run => ((body[i] & 0b00111111) + RUN_BIAS) as usize,
+                _ => 1,
+            };
+            let tally: &mut ChunkTally = histogram.tally_mut(&tag);
+            tally.chunks += 1;
+            tally.pixels += pixels;
+            tally.bytes += len;
+            count += pixels;
+            i += len;
+        }
+
+        if count != n_pixels {
+            return Err(ImgError::PixelCountMismatch {
+                expected: n_pixels,
+                actual: count,
+            });
+        }
+        Ok(histogram)
+    }
+
+    /// Decodes a QOI stream from `reader` chunk by chunk, without reading the whole file into memory first.
+    /// Decoding stops once the number of pixels declared in the header has been read, after which the end marker is expected.
+    /// `reader` is read in very small pieces, so unbuffered sources should be wrapped in a [std::io::BufReader].
+    /// Truncated streams result in an [ImgError::DecodeError].
+    /// ```rust
+    /// # use qoi::qoi_lib::*;
+    /// # use std::io::Cursor;
+    /// # fn main() -> Result<(), ImgError> {
+    /// let img: Image = Image::from_pixels(vec![Pixel::new(7, 7, 7, 255); 4], 2, 2, 4, 0)?;
+    /// let bytes: Vec<u8> = encode_from_image(&img);
+    /// let img: Image = decode_from_reader(&mut Cursor::new(bytes))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn decode_from_reader<R: Read>(reader: &mut R) -> Result<Image, ImgError> {
+        let mut head: [u8; 14] = [0; 14];
+        reader
+            .read_exact(&mut head)
+            .map_err(|_| ImgError::HeaderError)?;
+        let (width, height, channels, colorspace) = read_header(&head)?;
+        let n_pixels: usize = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or(ImgError::DecodeError)?;
+
+        let mut state: DecoderState = DecoderState::new();
+        let mut pixels: Vec<Pixel> = Vec::new();
+        let mut chunk: [u8; 5] = [0; 5];
+
+        while pixels.len() < n_pixels {
+            reader
+                .read_exact(&mut chunk[0..1])
+                .map_err(|_| ImgError::DecodeError)?;
+            let tag: ChunkType = read_tag(chunk[0])?;
+            let len: usize = chunk_len(&tag);
+            reader
+                .read_exact(&mut chunk[1..len])
+                .map_err(|_| ImgError::DecodeError)?;
+            let (dec_pix, count) = state.decode_chunk(&tag, &chunk[0..len]);
+            for j in 0..count {
+                pixels.push(dec_pix);
+            }
+        }
+
+        if pixels.len() != n_pixels {
+            return Err(ImgError::PixelCountMismatch {
+                expected: n_pixels,
+                actual: pixels.len(),
+            });
+        }
+
+        let mut end_bytes: [u8; 8] = [0; 8];
+        reader
+            .read_exact(&mut end_bytes)
+            .map_err(|_| ImgError::DecodeError)?;
+        if end_bytes != End::new().bytes {
+            debug!("Ending bytes not present.");
+            return Err(ImgError::DecodeError);
+        }
+
+        Image::from_pixels(pixels, width, height, channels, colorspace)
+    }
+
+    /// Decodes the QOI file at `path` through a read-only memory map instead of reading it into a buffer first,
+    /// so that only the decoded pixels take up heap memory. I/O errors are returned as [ImgError::IoError].
+    ///
+    /// The file must not be modified while it is being decoded.
+    #[cfg(feature = "mmap")]
+    pub fn decode_mmap<P: AsRef<Path>>(path: P) -> Result<Image, ImgError> {
+        let file: File = File::open(path).map_err(|e| ImgError::IoError(e.kind()))?;
+        //safe as long as no other process truncates or rewrites the file while it is mapped, see above
+        let map: memmap2::Mmap =
+            unsafe { memmap2::Mmap::map(&file) }.map_err(|e| ImgError::IoError(e.kind()))?;
+        decode_slice(&map)
+    }
+
+    /// Reads an uncompressed 24- or 32-bit BMP file into a 4-channel [Image].
+    /// Both bottom-up and top-down row orders are supported. 32-bit files whose alpha bytes are all zero are treated as opaque,
+    /// as most writers leave the fourth byte unused.
+    /// Returns [ImgError::HeaderError] for anything but uncompressed 24/32-bit BMPs and [ImgError::DataError] for truncated pixel data.
+    pub fn decode_bmp(bytes: &[u8]) -> Result<Image, ImgError> {
+        if bytes.len() < 54 || &bytes[0..2] != b"BM" {
+            return Err(ImgError::HeaderError);
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        //BITMAPINFOHEADER or one of its longer successors, not the 12-byte OS/2 BITMAPCOREHEADER
+        let info_len: usize = u32_at(14) as usize;
+        if info_len < 40 || info_len > bytes.len() - 14 {
+            return Err(ImgError::HeaderError);
+        }
+
+        let offset: usize = u32_at(10) as usize;
+        let width: i32 = u32_at(18) as i32;
+        let height: i32 = u32_at(22) as i32;
+        let bpp: u16 = u16_at(28);
+        let compression: u32 = u32_at(30);
+        //BI_RGB, or BI_BITFIELDS which 32-bit writers commonly use with the default BGRA masks
+        if width <= 0
+            || height == 0
+            || !(bpp == 24 || bpp == 32)
+            || !(compression == 0 || compression == 3)
+        {
+            return Err(ImgError::HeaderError);
+        }
+
+        let (width, top_down): (usize, bool) = (width as usize, height < 0);
+        let height: usize = height.unsigned_abs() as usize;
+        let pixel_len: usize = bpp as usize / 8;
+        //rows are padded to a multiple of 4 bytes
+        let stride: usize = (width * pixel_len).div_ceil(4) * 4;
+        match stride
+            .checked_mul(height)
+            .and_then(|len| len.checked_add(offset))
+        {
+            Some(end) if end <= bytes.len() => (),
+            _ => return Err(ImgError::DataError),
+        }
+
+        let mut data: Vec<u8> = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let row: usize = if top_down { y } else { height - 1 - y };
+            let start: usize = offset + row * stride;
+            for pixel in bytes[start..start + width * pixel_len].chunks_exact(pixel_len) {
+                let alpha: u8 = if pixel_len == 4 { pixel[3] } else { 255 };
+                data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], alpha]);
+            }
+        }
+        if pixel_len == 4 && data.iter().skip(3).step_by(4).all(|a| *a == 0) {
+            data.iter_mut().skip(3).step_by(4).for_each(|a| *a = 255);
+        }
+
+        Image::new(data, width as u32, height as u32, 4, 0)
+    }
+
+    /// Reads a PNG file into an sRGB [Image]. Palettes, grayscale, bit depths below 8 and 16-bit samples are
+    /// converted to 8-bit RGB, and interlaced files are deinterlaced. The image has 4 channels if the PNG carries
+    /// alpha, either as a channel or as transparency for a palette or colour key, and 3 channels otherwise.
+    /// Returns [ImgError::HeaderError] if `bytes` is not a PNG and [ImgError::DecodeError] if its image data is broken.
+    #[cfg(feature = "png")]
+    pub fn decode_png(bytes: &[u8]) -> Result<Image, ImgError> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|_| ImgError::HeaderError)?;
+        let mut buf: Vec<u8> = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buf)
+            .map_err(|_| ImgError::DecodeError)?;
+        let samples: &[u8] = &buf[..info.buffer_size()];
+
+        //after the transformations every sample has 8 bits and palettes are resolved, only grayscale is left to widen
+        let (data, channels): (Vec<u8>, u8) = match info.color_type {
+            png::ColorType::Rgb => (samples.to_vec(), 3),
+            png::ColorType::Rgba => (samples.to_vec(), 4),
+            png::ColorType::Grayscale => (samples.iter().flat_map(|&v| [v, v, v]).collect(), 3),
+            png::ColorType::GrayscaleAlpha => (
+                samples
+                    .chunks_exact(2)
+                    .flat_map(|va| [va[0], va[0], va[0], va[1]])
+                    .collect(),
+                4,
+            ),
+            //EXPAND turns indexed samples into rgb(a) before they reach the output buffer
+            png::ColorType::Indexed => {
+                unreachable!("png::Transformations::EXPAND leaves no indexed output")
+            }
+        };
+        Image::new(data, info.width, info.height, channels, 0)
+    }
+
+    //Splits a stream prefixed by its length as u32 (BE) off the front of `bytes`, returning it and the remainder
+    fn split_stream(bytes: &[u8]) -> Result<(&[u8], &[u8]), ImgError> {
+        let len_bytes: &[u8] = bytes.get(0..4).ok_or(ImgError::DecodeError)?;
+        let len: usize =
+            u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+        let stream: &[u8] = bytes.get(4..4 + len).ok_or(ImgError::DecodeError)?;
+        Ok((stream, &bytes[4 + len..]))
+    }
+
+    //Splits `n_streams` length-prefixed streams off the front of `bytes`
+    #[cfg(feature = "rayon")]
+    fn split_streams(mut bytes: &[u8], n_streams: usize) -> Result<Vec<&[u8]>, ImgError> {
+        let mut streams: Vec<&[u8]> = Vec::new();
+        for _ in 0..n_streams {
+            let (stream, rest) = split_stream(bytes)?;
+            streams.push(stream);
+            bytes = rest;
+        }
+        Ok(streams)
+    }
+
+    /// Magic bytes of the animated sequence container written by [encode_sequence].
+    pub const SEQUENCE_MAGIC: [u8; 4] = *b"qoia";
+
+    //Frame types of the sequence container: a complete image, or the residual against the previous frame
+    const KEY_FRAME: u8 = 0;
+    const DELTA_FRAME: u8 = 1;
+
+    //Pixels of `img` as a decoder reads them back, 3-channel images losing their alpha
+    fn stored_pixels(img: &Image) -> Vec<Pixel> {
+        match img.channels {
+            3 => img
+                .pixels
+                .iter()
+                .map(|pixel| Pixel { a: 255, ..*pixel })
+                .collect(),
+            _ => img.pixels.clone(),
+        }
+    }
+
+    /// Encodes `frames` into an animated sequence played back at `fps` frames per second, every frame being a keyframe.
+    ///
+    /// This is a **non-standard extension** of the QOI format which other decoders will not read. The container
+    /// consists of [SEQUENCE_MAGIC], the number of frames as `u32` (BE) and `fps` as `u16` (BE), followed by each
+    /// frame as a frame type byte (0 = keyframe, 1 = delta frame) and a complete standard QOI stream prefixed by its length
+    /// as `u32` (BE). Use [decode_sequence] to read it back and [encode_sequence_with_keyframes] to store delta frames.
+    pub fn encode_sequence(frames: &[Image], fps: u16) -> Vec<u8> {
+        encode_sequence_with_keyframes(frames, fps, 1)
+    }
+
+    /// Encodes `frames` like [encode_sequence], but only every `keyframe_interval`-th frame is stored as a complete image.
+    ///
+    /// The frames in between are delta frames: the channel-wise wrapping difference to the previous frame, stored as an
+    /// RGBA QOI stream. Regions that do not change become runs, which shrinks mostly static content such as screen
+    /// recordings considerably. A frame whose dimensions, channels or colorspace differ from the previous one is always
+    /// a keyframe. A `keyframe_interval` of 0 or 1 makes every frame a keyframe.
+    pub fn encode_sequence_with_keyframes(
+        frames: &[Image],
+        fps: u16,
+        keyframe_interval: usize,
+    ) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        out.extend_from_slice(&SEQUENCE_MAGIC);
+        out.extend_from_slice(&(frames.len() as u32).to_be_bytes());
+        out.extend_from_slice(&fps.to_be_bytes());
+        let mut prev: Option<&Image> = None;
+        for (i, frame) in frames.iter().enumerate() {
+            let delta: Option<&Image> = prev.filter(|prev| {
+                i % keyframe_interval.max(1) != 0
+                    && (prev.width, prev.height, prev.channels, prev.colorspace)
+                        == (frame.width, frame.height, frame.channels, frame.colorspace)
+            });
+            let (frame_type, stream): (u8, Vec<u8>) = match delta {
+                Some(prev) => {
+                    let residual: Vec<Pixel> = stored_pixels(frame)
+                        .iter()
+                        .zip(stored_pixels(prev))
+                        .map(|(cur, prev)| {
+                            Pixel::new(
+                                cur.r.wrapping_sub(prev.r),
+                                cur.g.wrapping_sub(prev.g),
+                                cur.b.wrapping_sub(prev.b),
+                                cur.a.wrapping_sub(prev.a),
+                            )
+                        })
+                        .collect();
+                    let residual: Image = Image {
+                        pixels: residual,
+                        channels: 4,
+                        ..*frame
+                    };
+                    (DELTA_FRAME, encode_from_image(&residual))
+                }
+                None => (KEY_FRAME, encode_from_image(frame)),
+            };
+            out.push(frame_type);
+            out.extend_from_slice(&(stream.len() as u32).to_be_bytes());
+            out.extend_from_slice(&stream);
+            prev = Some(frame);
+        }
+        out
+    }
+
+    /// Decodes an animated sequence produced by [encode_sequence] or [encode_sequence_with_keyframes], returning its
+    /// frames and frames per second.
+    /// Returns [ImgError::HeaderError] if `bytes` is not such a container and [ImgError::DecodeError] if it is truncated
+    /// or a delta frame does not fit the previous frame.
+    pub fn decode_sequence(bytes: &[u8]) -> Result<(Vec<Image>, u16), ImgError> {
+        if bytes.len() < 10 || bytes[0..4] != SEQUENCE_MAGIC {
+            return Err(ImgError::HeaderError);
+        }
+        let n_frames: usize = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let fps: u16 = u16::from_be_bytes([bytes[8], bytes[9]]);
+
+        let mut frames: Vec<Image> = Vec::new();
+        let mut rest: &[u8] = &bytes[10..];
+        for _ in 0..n_frames {
+            let (&frame_type, tail) = rest.split_first().ok_or(ImgError::DecodeError)?;
+            let (stream, tail) = split_stream(tail)?;
+            rest = tail;
+            let img: Image = decode_slice(stream)?;
+            let frame: Image = match (frame_type, frames.last()) {
+                (KEY_FRAME, _) => img,
+                (DELTA_FRAME, Some(prev))
+                    if (prev.width, prev.height) == (img.width, img.height) =>
+                {
+                    let pixels: Vec<Pixel> = prev
+                        .pixels
+                        .iter()
+                        .zip(img.pixels)
+                        .map(|(prev, residual)| {
+                            Pixel::new(
+                                prev.r.wrapping_add(residual.r),
+                                prev.g.wrapping_add(residual.g),
+                                prev.b.wrapping_add(residual.b),
+                                prev.a.wrapping_add(residual.a),
+                            )
+                        })
+                        .collect();
+                    Image { pixels, ..*prev }
+                }
+                _ => return Err(ImgError::DecodeError),
+            };
+            frames.push(frame);
+        }
+        Ok((frames, fps))
+    }
+
+    /// Magic bytes of the multi-stream container written by [encode_parallel].
+    #[cfg(feature = "rayon")]
+    pub const PARALLEL_MAGIC: [u8; 4] = *b"qoim";
+
+    /// Splits `img` into `tiles` horizontal bands and encodes them in parallel.
+    ///
+    /// The result is **not** a standard `.qoi` file: each band is an independent QOI stream (with its own header,
+    /// end marker and reset encoder state) and the streams are wrapped in a container made of [PARALLEL_MAGIC],
+    /// the number of bands as `u32` (BE) and each stream prefixed by its length as `u32` (BE).
+    /// Use [decode_parallel] to read it back.
+    #[cfg(feature = "rayon")]
+    pub fn encode_parallel(img: &Image, tiles: usize) -> Vec<u8> {
+        let width: usize = img.width.max(1) as usize;
+        let rows_per_tile: usize = (img.height as usize).div_ceil(tiles.max(1)).max(1);
+        //an image without pixels has no bands, a single empty stream keeps its dimensions and format
+        let streams: Vec<Vec<u8>> = if img.pixels.is_empty() {
+            vec![encode_from_image(img)]
+        } else {
+            img.pixels
+                .par_chunks(rows_per_tile * width)
+                .map(|band| {
+                    encode_from_image(&Image {
+                        pixels: band.to_vec(),
+                        height: (band.len() / width) as u32,
+                        ..*img
+                    })
+                })
+                .collect()
+        };
+
+        let mut out: Vec<u8> =
+            Vec::with_capacity(8 + streams.iter().map(|s| s.len() + 4).sum::<usize>());
+        out.extend_from_slice(&PARALLEL_MAGIC);
+        out.extend_from_slice(&(streams.len() as u32).to_be_bytes());
+        for stream in &streams {
+            out.extend_from_slice(&(stream.len() as u32).to_be_bytes());
+            out.extend_from_slice(stream);
+        }
+        out
+    }
+
+    /// Decodes the multi-stream container produced by [encode_parallel], decoding the bands in parallel.
+    /// Returns [ImgError::HeaderError] if `bytes` is not such a container and [ImgError::DecodeError] if the bands do not fit together.
+    #[cfg(feature = "rayon")]
+    pub fn decode_parallel(bytes: &[u8]) -> Result<Image, ImgError> {
+        if bytes.len() < 8 || bytes[0..4] != PARALLEL_MAGIC {
+            return Err(ImgError::HeaderError);
+        }
+        let n_streams: usize =
+            u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let streams: Vec<&[u8]> = split_streams(&bytes[8..], n_streams)?;
+
+        let bands: Vec<Image> = streams
+            .into_par_iter()
+            .map(decode_slice)
+            .collect::<Result<Vec<Image>, ImgError>>()?;
+        let Some(first) = bands.first() else {
+            return Err(ImgError::DecodeError);
+        };
+        if bands.iter().any(|band| band.width != first.width) {
+            return Err(ImgError::DecodeError);
+        }
+
+        let height: u32 = bands
+            .iter()
+            .try_fold(0u32, |height, band| height.checked_add(band.height))
+            .ok_or(ImgError::DecodeError)?;
+        let (width, channels, colorspace) = (first.width, first.channels, first.colorspace);
+        let pixels: Vec<Pixel> = bands.into_iter().flat_map(|band| band.pixels).collect();
+        Image::from_pixels(pixels, width, height, channels, colorspace)
+    }
+
+    #[cfg(test)]
+    mod tests {
+
+        use super::*;
+        use alloc::string::ToString;
+        #[cfg(feature = "std")]
+        use std::io;
+        #[cfg(feature = "std")]
+        use std::path::PathBuf;
+
+        //Walks the chunks of a QOI byte stream to find the start of the chunk containing `offset`
+        fn chunk_start(bytes: &[u8], offset: usize) -> usize {
+            let mut i: usize = 14;
+            while i < bytes.len() {
+                let len: usize = match read_tag(bytes[i]) {
+                    Ok(tag) => chunk_len(&tag),
+                    Err(_) => 1,
+                };
+                if offset < i + len {
+                    return i;
+                }
+                i += len;
+            }
+            offset
+        }
+
+        //Describes the first mismatch between two QOI byte streams, None if they are identical
+        fn stream_diff(expected: &[u8], actual: &[u8]) -> Option<String> {
+            let offset: usize = match expected.iter().zip(actual).position(|(e, a)| e != a) {
+                Some(pos) => pos,
+                None if expected.len() == actual.len() => return None,
+                None => expected.len().min(actual.len()),
+            };
+            let from: usize = offset.saturating_sub(4);
+            let mut report: String = format!(
+                "streams differ at byte {} (expected {} bytes, got {} bytes)\n",
+                offset,
+                expected.len(),
+                actual.len()
+            );
+            for (name, bytes) in [("expected", expected), ("actual", actual)] {
+                let to: usize = (offset + 5).min(bytes.len());
+                let window: &[u8] = &bytes[from.min(to)..to];
+                report.push_str(&format!("{:>8}: {:02x?}", name, window));
+                if offset >= 14 && offset < bytes.len() {
+                    let start: usize = chunk_start(bytes, offset);
+                    report.push_str(&format!(
+                        " chunk {:?} at byte {}",
+                        read_tag(bytes[start]),
+                        start
+                    ));
+                }
+                report.push('\n');
+            }
+            Some(report)
+        }
+
+        //Encodes every PNG in `dir` that has a reference `.qoi` of the same name next to it and compares the two.
+        //Encodings may differ, but must then decode to the same pixels. Returns the number of PNGs compared and how
+        //many of them were encoded byte for byte like the reference.
+        #[cfg(feature = "png")]
+        fn check_reference_images(dir: &Path) -> (usize, usize) {
+            let mut paths: Vec<PathBuf> = read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension() == Some(OsStr::new("png")))
+                .collect();
+            paths.sort();
+
+            let (mut compared, mut identical): (usize, usize) = (0, 0);
+            for png_path in paths {
+                let Ok(reference) = std::fs::read(png_path.with_extension("qoi")) else {
+                    continue;
+                };
+                let header: QoiHeader = decode_header(&reference).unwrap();
+                let img: Image = Image {
+                    colorspace: header.colorspace,
+                    ..decode_png(&std::fs::read(&png_path).unwrap()).unwrap()
+                };
+                let encoded: Vec<u8> = encode_from_image(&img);
+                compared += 1;
+                let Some(report) = stream_diff(&reference, &encoded) else {
+                    identical += 1;
+                    continue;
+                };
+
+                let expected: Image = decode_slice(&reference).unwrap();
+                let actual: Image = decode(encoded).unwrap();
+                assert_eq!(
+                    (actual.width, actual.height),
+                    (expected.width, expected.height),
+                    "{}\n{}",
+                    png_path.display(),
+                    report
+                );
+                assert!(
+                    actual.pixels == expected.pixels,
+                    "{} decodes to different pixels than its reference\n{}",
+                    png_path.display(),
+                    report
+                );
+            }
+            (compared, identical)
+        }
+
+        #[test]
+        #[ignore = "needs qoi_test_images"]
+        #[cfg(feature = "png")]
+        fn reference_conformance_test() {
+            //the official test images from https://qoiformat.org/qoi_test_images.zip, not part of the repository
+            let dir: &Path = Path::new("./qoi_test_images/");
+            let (compared, _) = check_reference_images(dir);
+            assert!(
+                compared > 0,
+                "no png with a reference qoi in {}",
+                dir.display()
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "png")]
+        fn check_reference_images_test() {
+            let dir: PathBuf = std::env::temp_dir().join("qoi_check_reference_images_test");
+            create_dir_all(&dir).unwrap();
+            let pixels: Vec<Pixel> = (0..30u8)
+                .map(|i| Pixel::new(i * 8, 100, 255 - i, 200 + i))
+                .collect();
+            let rgba: Image = Image::from_pixels(pixels.clone(), 6, 5, 4, 0).unwrap();
+            let rgb: Image = Image::from_pixels(pixels, 6, 5, 3, 1).unwrap();
+
+            //a byte-identical reference, a reference written by another encoder and a png without reference
+            rgba.write_png(dir.join("same")).unwrap();
+            std::fs::write(dir.join("same.qoi"), encode_from_image(&rgba)).unwrap();
+            rgb.write_png(dir.join("other")).unwrap();
+            std::fs::write(dir.join("other.qoi"), encode_raw(&rgb)).unwrap();
+            rgb.write_png(dir.join("lonely")).unwrap();
+            assert_eq!(check_reference_images(&dir), (2, 1));
+
+            //a reference with different pixels is a failure
+            let mut wrong: Image = rgb.clone();
+            wrong.set_pixel(0, 0, Pixel::new(1, 2, 3, 255)).unwrap();
+            std::fs::write(dir.join("other.qoi"), encode_from_image(&wrong)).unwrap();
+            let result = std::panic::catch_unwind(|| check_reference_images(&dir));
+            remove_dir_all(&dir).unwrap();
+            assert!(result.is_err());
+        }
+
+        #[test]
+        fn diff_test() {
+            let pix1: Pixel = Pixel::new(0, 0, 0, 255);
+            let pix2: Pixel = Pixel::new(255, 255, 255, 255);
+
+            let pix3: Pixel = Pixel::new(155, 155, 155, 255);
+            let pix4: Pixel = Pixel::new(160, 160, 160, 255);
+
+            assert_eq!(pix1.diff(&pix2), (1, 1, 1));
+            assert_eq!(pix2.diff(&pix1), (-1, -1, -1));
+            assert_eq!(pix4.diff(&pix3), (5, 5, 5));
+            assert_eq!(pix3.diff(&pix4), (-5, -5, -5));
+        }
+
+        #[test]
+        fn determine_chunk_test() {
+            let empty: [Pixel; 64] = [Pixel::new(0, 0, 0, 0); 64];
+            let prev: Pixel = Pixel::new(100, 100, 100, 255);
+            let shifted = |dr: i16, dg: i16, db: i16| {
+                Pixel::new((100 + dr) as u8, (100 + dg) as u8, (100 + db) as u8, 255)
+            };
+
+            //an exact match continues a run, even if the index holds the pixel too
+            let mut buffer: [Pixel; 64] = empty;
+            buffer[color_hash(&prev) as usize] = prev;
+            assert_eq!(prev.determine_chunk(&prev, &buffer), (ChunkType::Run, None));
+
+            //an index hit wins over every other op, including a change of alpha
+            let seen: Pixel = Pixel::new(7, 200, 31, 90);
+            buffer[color_hash(&seen) as usize] = seen;
+            assert_eq!(
+                seen.determine_chunk(&prev, &buffer),
+                (ChunkType::Index, Some((color_hash(&seen), 0, 0)))
+            );
+            //a different pixel in the same slot is no hit
+            let mut collision: [Pixel; 64] = empty;
+            collision[color_hash(&seen) as usize] = Pixel::new(1, 2, 3, 4);
+            assert_eq!(seen.determine_chunk(&prev, &collision).0, ChunkType::RGBA);
+
+            //Diff: each of dr, dg and db in -2..=1, stored with a bias of 2
+            assert_eq!(
+                shifted(-2, 0, 1).determine_chunk(&prev, &empty),
+                (ChunkType::Diff, Some((0, 2, 3)))
+            );
+            assert_eq!(
+                shifted(1, -2, -1).determine_chunk(&prev, &empty),
+                (ChunkType::Diff, Some((3, 0, 1)))
+            );
+            assert_eq!(
+                shifted(-1, 0, 0).determine_chunk(&prev, &empty),
+                (ChunkType::Diff, Some((1, 2, 2)))
+            );
+
+            //Luma: dg in -32..=31 with dr - dg and db - dg in -8..=7, stored with biases of 32 and 8
+            assert_eq!(
+                shifted(2, 0, 0).determine_chunk(&prev, &empty),
+                (ChunkType::Luma, Some((32, 10, 8)))
+            );
+            assert_eq!(
+                shifted(-3, 0, 0).determine_chunk(&prev, &empty),
+                (ChunkType::Luma, Some((32, 5, 8)))
+            );
+            assert_eq!(
+                shifted(-40, -32, -25).determine_chunk(&prev, &empty),
+                (ChunkType::Luma, Some((0, 0, 15)))
+            );
+            assert_eq!(
+                shifted(38, 31, 23).determine_chunk(&prev, &empty),
+                (ChunkType::Luma, Some((63, 15, 0)))
+            );
+
+            //RGB once any of those ranges is left
+            assert_eq!(
+                shifted(0, 32, 32).determine_chunk(&prev, &empty),
+                (ChunkType::RGB, None)
+            );
+            assert_eq!(
+                shifted(0, -33, -33).determine_chunk(&prev, &empty),
+                (ChunkType::RGB, None)
+            );
+            assert_eq!(
+                shifted(8, 0, 0).determine_chunk(&prev, &empty),
+                (ChunkType::RGB, None)
+            );
+            assert_eq!(
+                shifted(0, 0, -9).determine_chunk(&prev, &empty),
+                (ChunkType::RGB, None)
+            );
+            assert_eq!(
+                shifted(7, 0, -8).determine_chunk(&prev, &empty).0,
+                ChunkType::Luma
+            );
+
+            //any change of alpha needs RGBA, even with otherwise equal colour
+            assert_eq!(
+                Pixel::new(100, 100, 100, 254).determine_chunk(&prev, &empty),
+                (ChunkType::RGBA, None)
+            );
+            assert_eq!(
+                Pixel::new(101, 100, 100, 0).determine_chunk(&prev, &empty),
+                (ChunkType::RGBA, None)
+            );
+        }
+
+        #[test]
+        fn diff_wrap_boundary_test() {
+            let buffer: [Pixel; 64] = [Pixel::new(0, 0, 0, 0); 64];
+            let at = |r: u8, g: u8, b: u8| Pixel::new(r, g, b, 255);
+
+            //254 -> 1 wraps to +3, out of range for QOI_OP_DIFF but a luma chunk
+            assert_eq!(at(1, 1, 1).diff(&at(254, 254, 254)), (3, 3, 3));
+            assert_eq!(
+                at(1, 1, 1).determine_chunk(&at(254, 254, 254), &buffer),
+                (
+                    ChunkType::Luma,
+                    Some((3 + LUMA_BIAS_G, LUMA_BIAS_RB, LUMA_BIAS_RB))
+                )
+            );
+            assert_eq!(at(254, 254, 254).diff(&at(1, 1, 1)), (-3, -3, -3));
+            assert_eq!(
+                at(254, 254, 254).determine_chunk(&at(1, 1, 1), &buffer).0,
+                ChunkType::Luma
+            );
+
+            //the edges of the QOI_OP_DIFF range across the wrap
+            assert_eq!(
+                at(0, 254, 1).determine_chunk(&at(255, 0, 0), &buffer),
+                (
+                    ChunkType::Diff,
+                    Some((1 + DIFF_BIAS, DIFF_BIAS - 2, 1 + DIFF_BIAS))
+                )
+            );
+            assert_eq!(
+                at(2, 0, 0).determine_chunk(&at(0, 0, 0), &buffer).0,
+                ChunkType::Luma
+            );
+            //and of the luma range
+            assert_eq!(
+                at(224, 224, 224).determine_chunk(&at(0, 0, 0), &buffer).0,
+                ChunkType::Luma
+            );
+            assert_eq!(
+                at(223, 223, 223).determine_chunk(&at(0, 0, 0), &buffer).0,
+                ChunkType::RGB
+            );
+
+            //a difference of 128 is -128 like the spec's signed char, and too large for anything but QOI_OP_RGB
+            assert_eq!(at(128, 0, 0).diff(&at(0, 0, 0)), (-128, 0, 0));
+            assert_eq!(at(0, 0, 0).diff(&at(128, 0, 0)), (-128, 0, 0));
+            assert_eq!(
+                at(128, 0, 0).determine_chunk(&at(0, 0, 0), &buffer).0,
+                ChunkType::RGB
+            );
+
+            //and every one of these decodes back to the wrapped value
+            let pixels: Vec<Pixel> = vec![
+                at(254, 254, 254),
+                at(1, 1, 1),
+                at(254, 254, 254),
+                at(255, 0, 0),
+                at(0, 254, 1),
+                at(128, 126, 130),
+                at(0, 0, 0),
+            ];
+            let img: Image = Image::from_pixels(pixels.clone(), 7, 1, 4, 0).unwrap();
+            assert!(decode(encode_from_image(&img)).unwrap().pixels == pixels);
+        }
+
+        /* #[test]
+        fn qoi_to_qoi_test() -> io::Result<()> {
+            //Open path to test images
+            let path: &Path = Path::new("./qoi_test_images/");
+            let dir: ReadDir = match path.read_dir() {
+                Ok(d) => d,
+                Err(e) => panic!("Error reading path {e:?}"),
+            };
+            //Loop over files in directory, attempt to decode .qoi images and reencode 
+            for entry in dir {
+
+                let file_path = match entry {
+                    Ok(d) => d.path(),
+                    Err(e) => panic!("Non-functional dir entry! \n {e:?}")
+                };
+                let file_path_str = match file_path.to_str() {
+                    Some(s) => s,
+                    None => ""
+                };
+                if !(file_path_str.contains(".qoi")) {
+                    continue;
+                }
+                
+                let file = match File::open(&file_path) {
+                    Ok(f) => f,
+                    Err(e) => panic!("Error reading file with path {:?}", file_path_str),
+                };
+                let mut reader = BufReader::new(file);
+                let mut bytes: Vec<u8> = Vec::new();
+
+                reader.read_to_end(&mut bytes)?;
+
+                let output_image: super::Image;
+                match super::decode(bytes) {
+                    Ok(img) => output_image = img,
+                    Err(err) => panic!("Image decode failed for {:?}" , file_path.to_str())
+                }
+                let mut name = match file_path.file_name() {
+                    Some(s) => match s.to_str() {
+                        Some(ss) => ss,
+                        None => panic!("File Name Error!")
+                    },
+                    None => panic!("File Name Error!"),
+                };
+                name = match name.strip_suffix(".qoi") {
+                    Some(n) => n,
+                    None => name,
+                };
+                write_to_file(encode_from_image(output_image), name).expect("Writing image failed!");
+            }
+            
+            Ok(())
+        }
+
+        #[test]
+        fn png_to_qoi_test() -> io::Result<()> {
+            //Open path to test images
+            let path: &Path = Path::new("./qoi_test_images/");
+            let dir: ReadDir = match path.read_dir() {
+                Ok(d) => d,
+                Err(e) => panic!("Error reading path {e:?}"),
+            };
+            //Loop over files in directory, attempt to decode png and encode as qoi, compare to qoi
+            for entry in dir {
+
+                let file_path = match entry {
+                    Ok(d) => d.path(),
+                    Err(e) => panic!("Non-functional dir entry! \n {e:?}")
+                };
+                let file_path_str = match file_path.to_str() {
+                    Some(s) => s,
+                    None => ""
+                };
+                if !(file_path_str.contains(".png")) {
+                    continue;
+                }
+                debug!("{:}",file_path_str);
+                let file = match File::open(&file_path) {
+                    Ok(f) => f,
+                    Err(e) => panic!("Cannot read file! \n {e:?}")
+                };
+                let decoder = png::Decoder::new(file);
+                let mut reader = match decoder.read_info() {
+                    Ok(reader) => reader,
+                    Err(e) => panic!("ERROR: couldn't decode file: {e:}"),
+                };
+                //read image metadata
+                let width: u32 = reader.info().width;
+                let height: u32 = reader.info().height;
+                //for now: hardcoded to 4
+                let channels = match reader.info().color_type {
+                    ColorType::Rgb => 3,
+                    ColorType::Rgba => 4,
+                    _ => panic!("ERROR: Incompatible png file!")
+                };
+
+                //create buffer matching the size of png-decoder output, writing size to output
+                let mut buf = vec![0; reader.output_buffer_size()];
+                let info = match reader.next_frame(&mut buf) {
+                    Ok(i) => i,
+                    Err(e) => panic!("ERROR: {e:?}"),
+                };
+                let bytes = &buf[..info.buffer_size()];
+                let byte_vec: Vec<u8> = bytes.to_vec();
+
+                //create bitmap data from raw byte vector
+                let img: Image = match Image::new(byte_vec, width, height, channels, 0) {
+                    Ok(image) => image,
+                    Err(err) => panic!("Problem generating image: {:?}", err),
+                };
+
+                let encoded_buffer = super::encode_from_image(img);
+                let mut name =  match file_path.file_name() {
+                    None => panic!("whoops!"),
+                    Some(n) => match n.to_str() {
+                        None => panic!("im shiddin"),
+                        Some(s) => s, 
+                    },
+                };
+                name = match name.strip_suffix(".png") {
+                    Some(n) => n,
+                    None => name,
+                };
+                write_to_file(encoded_buffer,name ).expect("Can't write resulting file!");
+            }
+            
+            Ok(())
+        }
+ */
+        #[test]
+        fn with_background_test() {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(255, 0, 0, 128),
+                Pixel::new(0, 0, 255, 0),
+                Pixel::new(0, 200, 0, 255),
+                Pixel::new(0, 0, 0, 64),
+            ];
+            let img: Image = Image::from_pixels(pixels, 2, 2, 4, 0).unwrap();
+            let flat: Image = img.with_background(Pixel::new(255, 255, 255, 255));
+
+            assert_eq!(flat.channels, 3);
+            assert!(flat.pixels.iter().all(|p| p.a == 255));
+            assert_eq!(flat.pixels[0], Pixel::new(255, 127, 127, 255));
+            assert_eq!(flat.pixels[1], Pixel::new(255, 255, 255, 255));
+            assert_eq!(flat.pixels[2], Pixel::new(0, 200, 0, 255));
+            assert_eq!(flat.pixels[3], Pixel::new(191, 191, 191, 255));
+        }
+
+        #[test]
+        fn blend_over_test() {
+            let pixels: Vec<Pixel> = vec![Pixel::new(255, 0, 0, 128), Pixel::new(10, 20, 30, 255)];
+            let img: Image = Image::from_pixels(pixels, 2, 1, 4, 1).unwrap();
+            let blended: Image = img.blend_over(&Pixel::new(255, 255, 255, 0));
+            assert_eq!((blended.channels, blended.colorspace), (4, 1));
+            assert_eq!(blended.pixels[0], Pixel::new(255, 127, 127, 255));
+            assert_eq!(blended.pixels[1], img.pixels[1]);
+        }
+
+        #[test]
+        fn solid_test() {
+            let red: Pixel = Pixel::new(255, 0, 0, 255);
+            let img: Image = Image::solid(3, 2, red, 4, 1).unwrap();
+            assert_eq!(
+                (img.width, img.height, img.channels, img.colorspace),
+                (3, 2, 4, 1)
+            );
+            assert_eq!(img.pixels, vec![red; 6]);
+
+            let mut canvas: Image = Image::blank(2, 2, 4, 0).unwrap();
+            assert!(canvas.pixels.iter().all(|p| *p == Pixel::new(0, 0, 0, 0)));
+            canvas.set_pixel(1, 1, red).unwrap();
+            assert_eq!(canvas.get_pixel(1, 1), Some(red));
+            assert!(Image::blank(0, 5, 3, 0).unwrap().pixels.is_empty());
+
+            //invalid formats are rejected like in Image::from_pixels
+            assert_eq!(
+                Image::solid(1, 1, red, 7, 9).err(),
+                Some(ImgError::InvalidFormat {
+                    channels: 7,
+                    colorspace: 9
+                })
+            );
+            assert_eq!(
+                Image::blank(1, 1, 4, 2).err(),
+                Some(ImgError::InvalidFormat {
+                    channels: 4,
+                    colorspace: 2
+                })
+            );
+            //u32::MAX² pixels cannot be allocated, which is an error rather than a panic
+            assert_eq!(
+                Image::blank(u32::MAX, u32::MAX, 4, 0).err(),
+                Some(ImgError::DataError)
+            );
+        }
+
+        #[test]
+        fn rows_test() {
+            let pixels: Vec<Pixel> = (0..12).map(|i| Pixel::new(i, 0, 0, 255)).collect();
+            let mut img: Image = Image::from_pixels(pixels, 4, 3, 4, 0).unwrap();
+            assert_eq!(img.rows().len(), 3);
+            assert!(img.rows().all(|row| row.len() == 4));
+            assert_eq!(img.rows().nth(1).unwrap()[0], Pixel::new(4, 0, 0, 255));
+
+            for (y, row) in img.rows_mut().enumerate() {
+                row[0] = Pixel::new(0, y as u8, 0, 255);
+            }
+            assert_eq!(img.get_pixel(0, 2), Some(Pixel::new(0, 2, 0, 255)));
+            assert_eq!(img.get_pixel(1, 2), Some(Pixel::new(9, 0, 0, 255)));
+            assert_eq!(Image::blank(0, 0, 4, 0).unwrap().rows().count(), 0);
+        }
+
+        #[test]
+        fn image_diff_test() {
+            let pixels: Vec<Pixel> = (0..12)
+                .map(|i| Pixel::new(i * 20, 255 - i, 7, 100 + i))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 4, 3, 4, 0).unwrap();
+            let same: Image = img.diff(&img).unwrap();
+            assert!(same.pixels.iter().all(|p| *p == Pixel::new(0, 0, 0, 255)));
+            assert_eq!(img.max_channel_diff(&img), 0);
+
+            let mut other: Image = img.clone();
+            other.set_pixel(1, 1, Pixel::new(0, 255, 10, 100)).unwrap();
+            let diff: Image = img.diff(&other).unwrap();
+            assert_eq!(diff.get_pixel(1, 1), Some(Pixel::new(100, 5, 3, 255)));
+            assert_eq!(
+                diff.pixels
+                    .iter()
+                    .filter(|p| **p != Pixel::new(0, 0, 0, 255))
+                    .count(),
+                1
+            );
+            assert_eq!(img.max_channel_diff(&other), 100);
+
+            let wide: Image = img.resize(6, 2);
+            assert_eq!(img.diff(&wide).err(), Some(ImgError::DataError));
+            assert_eq!(img.max_channel_diff(&wide), 255);
+        }
+
+        #[test]
+        fn from_pixels_count_test() {
+            let pixels: Vec<Pixel> = vec![Pixel::new(0, 0, 0, 255); 5];
+            assert!(Image::from_pixels(pixels.clone(), 5, 1, 4, 0).is_ok());
+            assert_eq!(
+                Image::from_pixels(pixels, 3, 2, 4, 0).err(),
+                Some(ImgError::PixelNumberError)
+            );
+        }
+
+        #[test]
+        fn get_set_pixel_test() {
+            let mut img: Image =
+                Image::from_pixels(vec![Pixel::new(0, 0, 0, 255); 12], 4, 3, 4, 0).unwrap();
+            let corners: [(u32, u32); 4] = [(0, 0), (3, 0), (0, 2), (3, 2)];
+            for (i, (x, y)) in corners.into_iter().enumerate() {
+                img.set_pixel(x, y, Pixel::new(i as u8, 0, 0, 255)).unwrap();
+            }
+            for (i, (x, y)) in corners.into_iter().enumerate() {
+                assert_eq!(img.get_pixel(x, y), Some(Pixel::new(i as u8, 0, 0, 255)));
+            }
+            assert_eq!(img.pixels[11], Pixel::new(3, 0, 0, 255));
+
+            assert_eq!(img.get_pixel(4, 0), None);
+            assert_eq!(img.get_pixel(0, 3), None);
+            assert_eq!(
+                img.set_pixel(4, 2, Pixel::new(1, 1, 1, 1)),
+                Err(ImgError::DataError)
+            );
+        }
+
+        #[test]
+        fn image_getters_test() {
+            let img: Image = decode(encode_from_image(
+                &Image::from_pixels(vec![Pixel::new(9, 8, 7, 255); 6], 3, 2, 3, 1).unwrap(),
+            ))
+            .unwrap();
+            assert_eq!((img.width(), img.height()), (3, 2));
+            assert_eq!((img.channels(), img.colorspace()), (3, 1));
+            assert_eq!(img.pixels(), &[Pixel::new(9, 8, 7, 255); 6][..]);
+        }
+
+        #[test]
+        fn histogram_test() {
+            let img: Image = Image::solid(7, 3, Pixel::new(10, 20, 30, 40), 4, 0).unwrap();
+            let histogram: [[u32; 256]; 4] = img.histogram();
+            for (c, value) in [10usize, 20, 30, 40].into_iter().enumerate() {
+                assert_eq!(histogram[c][value], 21);
+                assert_eq!(histogram[c].iter().filter(|&&n| n > 0).count(), 1);
+            }
+
+            let pixels: Vec<Pixel> = (0..=255u8).map(|v| Pixel::new(v, v / 2, 0, 255)).collect();
+            let histogram: [[u32; 256]; 4] = Image::from_pixels(pixels, 16, 16, 3, 0)
+                .unwrap()
+                .histogram();
+            assert!(histogram[0].iter().all(|&n| n == 1));
+            assert!(histogram[1][..128].iter().all(|&n| n == 2));
+            assert_eq!((histogram[2][0], histogram[3][255]), (256, 256));
+            assert!(Image::blank(0, 0, 4, 0)
+                .unwrap()
+                .histogram()
+                .iter()
+                .flatten()
+                .all(|&n| n == 0));
+        }
+
+        #[test]
+        fn channel_stats_test() {
+            //horizontal red gradient 10, 20, .., 80 over two rows
+            let mut pixels: Vec<Pixel> = Vec::new();
+            for y in 0..2 {
+                for x in 1..=8 {
+                    pixels.push(Pixel::new(x * 10, 50, y * 100, 255));
+                }
+            }
+            let img: Image = Image::from_pixels(pixels, 8, 2, 4, 0).unwrap();
+            let [red, green, blue, alpha] = img.channel_stats();
+
+            assert_eq!((red.min, red.max, red.mean), (10, 80, 45.0));
+            assert_eq!((green.min, green.max, green.mean), (50, 50, 50.0));
+            assert_eq!((blue.min, blue.max, blue.mean), (0, 100, 50.0));
+            assert_eq!((alpha.min, alpha.max), (255, 255));
+            assert_eq!(Pixel::new(1, 2, 3, 4).channels(), (1, 2, 3, 4));
+        }
+
+        #[test]
+        fn pixel_getters_test() {
+            let pix: Pixel = Pixel::new(1, 2, 3, 4);
+            assert_eq!((pix.r(), pix.g(), pix.b(), pix.a()), (1, 2, 3, 4));
+            let (r, g, b, a) = pix.channels();
+            assert_eq!([r, g, b, a], [1, 2, 3, 4]);
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn apply_kernel_test() {
+            let mut pixels: Vec<Pixel> = Vec::new();
+            for i in 0..5 * 4 {
+                pixels.push(Pixel::new(i * 13, 255 - i * 7, i.wrapping_mul(i), 100 + i));
+            }
+            let img: Image = Image::from_pixels(pixels, 5, 4, 4, 0).unwrap();
+
+            let identity: Image = img.apply_kernel(KERNEL_IDENTITY, 1.0, 0.0);
+            assert_eq!(identity.pixels, img.pixels);
+
+            let kernel_blur: Image = img.apply_kernel(KERNEL_BOX_BLUR, 9.0, 0.0);
+            let box_blur: Image = img.blur_box(1);
+            for (a, b) in kernel_blur.pixels.iter().zip(box_blur.pixels.iter()) {
+                assert_eq!(a.a, b.a);
+                assert!(a.r.abs_diff(b.r) <= 1 && a.g.abs_diff(b.g) <= 1 && a.b.abs_diff(b.b) <= 1);
+            }
+
+            //radii beyond the image size are limited to it instead of overflowing the sums
+            assert!(img.blur_box(u32::MAX) == img.blur_box(5));
+            let white: Image = Image::solid(3, 2, Pixel::new(255, 255, 255, 9), 4, 0).unwrap();
+            assert!(white.blur_box(70_000) == white);
+        }
+
+        #[test]
+        fn resize_test() {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(1, 0, 0, 255),
+                Pixel::new(2, 0, 0, 255),
+                Pixel::new(3, 0, 0, 255),
+                Pixel::new(4, 0, 0, 255),
+            ];
+            let img: Image = Image::from_pixels(pixels, 2, 2, 4, 0).unwrap();
+
+            let up: Image = img.resize(4, 4);
+            assert_eq!((up.width, up.height), (4, 4));
+            let reds: Vec<u8> = up.pixels.iter().map(|p| p.r).collect();
+            assert_eq!(reds, vec![1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+
+            let down: Image = up.resize(2, 2);
+            assert_eq!((down.width, down.height), (2, 2));
+            assert_eq!(down.pixels, img.pixels);
+            assert_eq!(img.resize(2, 2).pixels, img.pixels);
+
+            let empty: Image = img.resize(0, 3);
+            assert_eq!((empty.width, empty.height), (0, 3));
+            assert!(empty.pixels.is_empty());
+
+            //an image without pixels can be scaled up again, there is just nothing to sample
+            let regrown: Image = img.resize(0, 2).resize(4, 4);
+            assert_eq!((regrown.width, regrown.height, regrown.channels), (4, 4, 4));
+            assert_eq!(regrown.pixels, vec![Pixel::new(0, 0, 0, 0); 16]);
+            assert!(decode(encode_from_image(&regrown)).unwrap() == regrown);
+        }
+
+        #[test]
+        fn crop_test() {
+            let pixels: Vec<Pixel> = (0..16).map(|i| Pixel::new(i, 0, 0, 255)).collect();
+            let img: Image = Image::from_pixels(pixels, 4, 4, 3, 1).unwrap();
+
+            let center: Image = img.crop(1, 1, 2, 2).unwrap();
+            assert_eq!((center.width, center.height), (2, 2));
+            assert_eq!((center.channels, center.colorspace), (3, 1));
+            let reds: Vec<u8> = center.pixels.iter().map(|p| p.r).collect();
+            assert_eq!(reds, vec![5, 6, 9, 10]);
+
+            assert_eq!(img.crop(3, 0, 2, 1).err(), Some(ImgError::DataError));
+            assert_eq!(img.crop(0, 2, 1, 3).err(), Some(ImgError::DataError));
+            assert_eq!(img.crop(u32::MAX, 0, 2, 1).err(), Some(ImgError::DataError));
+        }
+
+        #[test]
+        fn flip_test() {
+            //3x2, asymmetric along both axes
+            let pixels: Vec<Pixel> = (0..6).map(|i| Pixel::new(i, 0, 0, 255)).collect();
+            let img: Image = Image::from_pixels(pixels, 3, 2, 4, 0).unwrap();
+            let reds = |img: &Image| -> Vec<u8> { img.pixels.iter().map(|p| p.r).collect() };
+
+            let horizontal: Image = img.flip_horizontal();
+            assert_eq!(reds(&horizontal), vec![2, 1, 0, 5, 4, 3]);
+            assert_eq!(horizontal.flip_horizontal().pixels, img.pixels);
+
+            let vertical: Image = img.flip_vertical();
+            assert_eq!(reds(&vertical), vec![3, 4, 5, 0, 1, 2]);
+            assert_eq!(vertical.flip_vertical().pixels, img.pixels);
+        }
+
+        #[test]
+        fn rotate_test() {
+            //3x2:  0 1 2
+            //      3 4 5
+            let pixels: Vec<Pixel> = (0..6).map(|i| Pixel::new(i, 0, 0, 255)).collect();
+            let img: Image = Image::from_pixels(pixels, 3, 2, 4, 0).unwrap();
+            let reds = |img: &Image| -> Vec<u8> { img.pixels.iter().map(|p| p.r).collect() };
+
+            let cw: Image = img.rotate90();
+            assert_eq!((cw.width, cw.height), (2, 3));
+            assert_eq!(reds(&cw), vec![3, 0, 4, 1, 5, 2]);
+
+            let half: Image = img.rotate180();
+            assert_eq!((half.width, half.height), (3, 2));
+            assert_eq!(reds(&half), vec![5, 4, 3, 2, 1, 0]);
+
+            let ccw: Image = img.rotate270();
+            assert_eq!((ccw.width, ccw.height), (2, 3));
+            assert_eq!(reds(&ccw), vec![2, 5, 1, 4, 0, 3]);
+
+            assert_eq!(cw.rotate270().pixels, img.pixels);
+            assert_eq!(cw.rotate90().pixels, half.pixels);
+        }
+
+        #[cfg(feature = "image")]
+        #[test]
+        fn image_crate_test() {
+            let pixels: Vec<Pixel> = (0..6).map(|i| Pixel::new(i, 2 * i, 3 * i, 100)).collect();
+            let rgb: Image = Image::from_pixels(pixels.clone(), 3, 2, 3, 0).unwrap();
+            let rgba: Image = Image::from_pixels(pixels, 3, 2, 4, 0).unwrap();
+
+            let buffer: image::RgbaImage = image::RgbaImage::from(&rgb);
+            assert_eq!(buffer.dimensions(), (3, 2));
+            assert_eq!(buffer.get_pixel(2, 1).0, [5, 10, 15, 255]);
+            assert_eq!(
+                image::RgbaImage::from(&rgba).get_pixel(2, 1).0,
+                [5, 10, 15, 100]
+            );
+
+            let dynamic: image::DynamicImage = image::DynamicImage::from(&rgb);
+            assert_eq!(dynamic.color(), image::ColorType::Rgb8);
+            let back: Image = Image::from_dynamic_image(&dynamic);
+            assert_eq!((back.width, back.height, back.channels), (3, 2, 3));
+            assert_eq!(back.pixels[5], Pixel::new(5, 10, 15, 255));
+
+            let back: Image = Image::from_dynamic_image(&image::DynamicImage::from(&rgba));
+            assert_eq!(back.channels, 4);
+            assert_eq!(back.pixels, rgba.pixels);
+        }
+
+        #[test]
+        #[cfg(feature = "png")]
+        fn write_png_test() {
+            let dir: PathBuf = std::env::temp_dir().join("qoi_write_png_test");
+            create_dir_all(&dir).unwrap();
+            let pixels: Vec<Pixel> = (0..6).map(|i| Pixel::new(i, 0, 0, 255)).collect();
+
+            for channels in [3, 4] {
+                let img: Image = Image::from_pixels(pixels.clone(), 3, 2, channels, 0).unwrap();
+                let path: PathBuf = dir.join(format!("img{channels}"));
+                img.write_png(&path).unwrap();
+
+                let file: File = File::open(path.with_extension("png")).unwrap();
+                let reader = png::Decoder::new(file).read_info().unwrap();
+                let info = reader.info();
+                assert_eq!((info.width, info.height), (3, 2));
+                let color: png::ColorType = match channels {
+                    3 => png::ColorType::Rgb,
+                    _ => png::ColorType::Rgba,
+                };
+                assert_eq!(info.color_type, color);
+            }
+
+            let missing: PathBuf = dir.join("missing").join("img.png");
+            assert_eq!(
+                Image::from_pixels(pixels, 3, 2, 4, 0)
+                    .unwrap()
+                    .write_png(missing),
+                Err(ImgError::IoError(io::ErrorKind::NotFound))
+            );
+            remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        #[cfg(feature = "png")]
+        fn rgb_png_roundtrip_test() {
+            //reads the 8-bit samples and colour type of a PNG stream
+            fn read_png(bytes: &[u8]) -> (Vec<u8>, png::ColorType) {
+                let mut reader = png::Decoder::new(bytes).read_info().unwrap();
+                let mut buf: Vec<u8> = vec![0; reader.output_buffer_size()];
+                let info = reader.next_frame(&mut buf).unwrap();
+                buf.truncate(info.buffer_size());
+                (buf, info.color_type)
+            }
+
+            //alpha is dropped by 3-channel images and comes back as 255
+            let pixels: Vec<Pixel> = (0..20u8)
+                .map(|i| Pixel::new(i * 12, 255 - i * 7, i * 3, i))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 5, 4, 3, 0).unwrap();
+            let mut png_bytes: Vec<u8> = Vec::new();
+            img.write_png_to(&mut png_bytes).unwrap();
+            let (rgb, color) = read_png(&png_bytes);
+            assert_eq!(color, png::ColorType::Rgb);
+            assert_eq!(rgb, img.to_bytes());
+            assert_eq!(rgb.len(), 5 * 4 * 3);
+
+            let from_png: Image = Image::new(rgb.clone(), 5, 4, 3, 0).unwrap();
+            let decoded: Image = decode(encode_from_image(&from_png)).unwrap();
+            assert_eq!(decoded.channels(), 3);
+            assert!(decoded.pixels.iter().all(|pixel| pixel.a == 255));
+            assert_eq!(decoded.to_bytes(), rgb);
+
+            let mut again: Vec<u8> = Vec::new();
+            decoded.write_png_to(&mut again).unwrap();
+            assert_eq!(read_png(&again), (rgb, png::ColorType::Rgb));
+        }
+
+        #[test]
+        #[cfg(feature = "png")]
+        fn decode_png_test() {
+            //writes a PNG with the given layout, the palette and transparency only for indexed PNGs
+            fn png_bytes(
+                width: u32,
+                color: png::ColorType,
+                depth: png::BitDepth,
+                palette: Option<(&[u8], &[u8])>,
+                data: &[u8],
+            ) -> Vec<u8> {
+                let mut bytes: Vec<u8> = Vec::new();
+                let mut encoder = png::Encoder::new(&mut bytes, width, 2);
+                encoder.set_color(color);
+                encoder.set_depth(depth);
+                if let Some((palette, trns)) = palette {
+                    encoder.set_palette(palette);
+                    encoder.set_trns(trns);
+                }
+                let mut writer = encoder.write_header().unwrap();
+                writer.write_image_data(data).unwrap();
+                writer.finish().unwrap();
+                bytes
+            }
+
+            //a 2-bit palette with one transparent entry: 4x2 pixels, one byte per row
+            let palette: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9];
+            let bytes: Vec<u8> = png_bytes(
+                4,
+                png::ColorType::Indexed,
+                png::BitDepth::Two,
+                Some((&palette, &[255, 255, 0])),
+                &[0b00_01_10_11, 0b11_10_01_00],
+            );
+            let img: Image = decode_png(&bytes).unwrap();
+            assert_eq!(
+                (img.width, img.height, img.channels, img.colorspace),
+                (4, 2, 4, 0)
+            );
+            let red: Pixel = Pixel::new(255, 0, 0, 255);
+            let green: Pixel = Pixel::new(0, 255, 0, 255);
+            let clear: Pixel = Pixel::new(0, 0, 255, 0);
+            let grey: Pixel = Pixel::new(9, 9, 9, 255);
+            assert_eq!(
+                img.pixels,
+                vec![red, green, clear, grey, grey, clear, green, red]
+            );
+            assert!(decode(encode_from_image(&img)).unwrap() == img);
+
+            //an opaque palette stays 3-channel
+            let bytes: Vec<u8> = png_bytes(
+                2,
+                png::ColorType::Indexed,
+                png::BitDepth::Eight,
+                Some((&palette, &[])),
+                &[3, 1, 0, 2],
+            );
+            let img: Image = decode_png(&bytes).unwrap();
+            assert_eq!(img.channels, 3);
+            assert_eq!(img.to_bytes(), [9, 9, 9, 0, 255, 0, 255, 0, 0, 0, 0, 255]);
+
+            //16-bit samples keep their high byte
+            let data: Vec<u8> = (0..32u8).collect();
+            let bytes: Vec<u8> =
+                png_bytes(2, png::ColorType::Rgba, png::BitDepth::Sixteen, None, &data);
+            let img: Image = decode_png(&bytes).unwrap();
+            assert_eq!(img.channels, 4);
+            assert_eq!(
+                img.to_bytes(),
+                data.iter().step_by(2).copied().collect::<Vec<u8>>()
+            );
+
+            //grayscale is widened to rgb
+            let bytes: Vec<u8> = png_bytes(
+                2,
+                png::ColorType::Grayscale,
+                png::BitDepth::Eight,
+                None,
+                &[0, 50, 100, 255],
+            );
+            let img: Image = decode_png(&bytes).unwrap();
+            assert_eq!(img.channels, 3);
+            assert_eq!(img.pixels[1], Pixel::new(50, 50, 50, 255));
+            let bytes: Vec<u8> = png_bytes(
+                1,
+                png::ColorType::GrayscaleAlpha,
+                png::BitDepth::Eight,
+                None,
+                &[7, 8, 9, 10],
+            );
+            assert_eq!(
+                decode_png(&bytes).unwrap().pixels,
+                vec![Pixel::new(7, 7, 7, 8), Pixel::new(9, 9, 9, 10)]
+            );
+
+            assert_eq!(decode_png(b"not a png").err(), Some(ImgError::HeaderError));
+            assert_eq!(
+                decode_png(&bytes[..bytes.len() - 20]).err(),
+                Some(ImgError::DecodeError)
+            );
+
+            //Adam7 interlaced rgb, written by hand as the png encoder only produces non-interlaced files
+            let (width, height): (u32, u32) = (5, 3);
+            let pixel = |x: u32, y: u32| [x as u8 * 40, y as u8 * 80, 7];
+            let mut raw: Vec<u8> = Vec::new();
+            for (x0, y0, dx, dy) in [
+                (0, 0, 8, 8),
+                (4, 0, 8, 8),
+                (0, 4, 4, 8),
+                (2, 0, 4, 4),
+                (0, 2, 2, 4),
+                (1, 0, 2, 2),
+                (0, 1, 1, 2),
+            ] {
+                for y in (y0..height).step_by(dy) {
+                    if x0 < width {
+                        //filter type none, then the pixels of this pass in the row
+                        raw.push(0);
+                        (x0..width)
+                            .step_by(dx)
+                            .for_each(|x| raw.extend(pixel(x, y)));
+                    }
+                }
+            }
+            let crc32 = |bytes: &[u8]| -> u32 {
+                !bytes.iter().fold(!0u32, |crc, &b| {
+                    (0..8).fold(crc ^ b as u32, |c, _| {
+                        (c >> 1) ^ (0xEDB8_8320 & (c & 1).wrapping_neg())
+                    })
+                })
+            };
+            let (a, b): (u32, u32) = raw.iter().fold((1, 0), |(a, b), &v| {
+                let a: u32 = (a + v as u32) % 65521;
+                (a, (b + a) % 65521)
+            });
+            //zlib stream made of a single stored deflate block
+            let mut idat: Vec<u8> = vec![0x78, 0x01, 0x01];
+            idat.extend((raw.len() as u16).to_le_bytes());
+            idat.extend((!(raw.len() as u16)).to_le_bytes());
+            idat.extend(&raw);
+            idat.extend(((b << 16) | a).to_be_bytes());
+            let mut ihdr: Vec<u8> = [width.to_be_bytes(), height.to_be_bytes()].concat();
+            ihdr.extend([8, 2, 0, 0, 1]);
+
+            let mut interlaced: Vec<u8> = b"\x89PNG\r\n\x1a\n".to_vec();
+            for (kind, data) in [(b"IHDR", ihdr), (b"IDAT", idat), (b"IEND", Vec::new())] {
+                let chunk: Vec<u8> = [&kind[..], &data].concat();
+                interlaced.extend((data.len() as u32).to_be_bytes());
+                interlaced.extend(&chunk);
+                interlaced.extend(crc32(&chunk).to_be_bytes());
+            }
+            let img: Image = decode_png(&interlaced).unwrap();
+            assert_eq!((img.width, img.height, img.channels), (5, 3, 3));
+            let expected: Vec<u8> = (0..height)
+                .flat_map(|y| (0..width).flat_map(move |x| pixel(x, y)))
+                .collect();
+            assert_eq!(img.to_bytes(), expected);
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn write_to_file_test() -> io::Result<()> {
+            //the path is used exactly as given, with or without extension
+            let dir: PathBuf = std::env::temp_dir().join("qoi_write_to_file_test");
+            create_dir_all(&dir)?;
+            for name in ["image", "image.qoi", "image.bin"] {
+                write_to_file(vec![1, 2, 3], dir.join(name))?;
+                assert_eq!(read(dir.join(name))?, [1, 2, 3]);
+            }
+            let mut names: Vec<String> = read_dir(&dir)?
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            assert_eq!(names, ["image", "image.bin", "image.qoi"]);
+
+            assert_eq!(with_qoi_extension("out"), PathBuf::from("out.qoi"));
+            assert_eq!(with_qoi_extension("out.qoi"), PathBuf::from("out.qoi"));
+            assert_eq!(with_qoi_extension("out.bin"), PathBuf::from("out.bin"));
+            assert_eq!(with_qoi_extension("dir.d/out"), PathBuf::from("dir.d/out.qoi"));
+            write_to_file(vec![4], with_qoi_extension(dir.join("image")))?;
+            assert_eq!(read(dir.join("image.qoi"))?, [4]);
+            remove_dir_all(&dir)
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn write_to_file_no_clobber_test() -> io::Result<()> {
+            let path: PathBuf = std::env::temp_dir().join("qoi_no_clobber_test.qoi");
+            let _ = remove_file(&path);
+
+            write_to_file_no_clobber(vec![1, 2, 3], &path)?;
+            assert_eq!(read(&path)?, [1, 2, 3]);
+            let err: io::Error = write_to_file_no_clobber(vec![4], &path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+            assert_eq!(read(&path)?, [1, 2, 3]);
+
+            write_to_file(vec![5, 6], &path)?;
+            assert_eq!(read(&path)?, [5, 6]);
+            remove_file(&path)
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn write_ppm_test() -> io::Result<()> {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(255, 0, 0, 255),
+                Pixel::new(0, 255, 0, 128),
+                Pixel::new(0, 0, 255, 0),
+                Pixel::new(1, 2, 3, 255),
+            ];
+            let img: Image = Image::from_pixels(pixels, 2, 2, 4, 0).unwrap();
+            let path: PathBuf = std::env::temp_dir().join("qoi_write_ppm_test.ppm");
+            img.write_ppm(&path)?;
+
+            let bytes: Vec<u8> = read(&path)?;
+            remove_file(&path)?;
+            assert_eq!(&bytes[..11], b"P6\n2 2\n255\n");
+            assert_eq!(&bytes[11..], &[255, 0, 0, 0, 255, 0, 0, 0, 255, 1, 2, 3]);
+            Ok(())
+        }
+
+        //2x2 24-bit bottom-up BMP: red, green / blue, white, each row padded to 8 bytes
+        #[rustfmt::skip]
+        const BMP_24: [u8; 70] = [
+            b'B', b'M', 70, 0, 0, 0, 0, 0, 0, 0, 54, 0, 0, 0, //file header
+            40, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 24, 0, //info header
+            0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            255, 0, 0, 255, 255, 255, 0, 0, //bottom row: blue, white
+            0, 0, 255, 0, 255, 0, 0, 0, //top row: red, green
+        ];
+
+        #[test]
+        fn decode_bmp_test() {
+            let expected: Vec<Pixel> = vec![
+                Pixel::new(255, 0, 0, 255),
+                Pixel::new(0, 255, 0, 255),
+                Pixel::new(0, 0, 255, 255),
+                Pixel::new(255, 255, 255, 255),
+            ];
+            let img: Image = decode_bmp(&BMP_24).unwrap();
+            assert_eq!((img.width, img.height, img.channels), (2, 2, 4));
+            assert_eq!(img.pixels, expected);
+            let decoded: Image = decode(encode_from_image(&img)).unwrap();
+            assert_eq!(decoded.pixels, expected);
+
+            //same image as 32-bit top-down with an alpha channel
+            let mut bmp_32: Vec<u8> = BMP_24[..54].to_vec();
+            bmp_32[22..26].copy_from_slice(&(-2i32).to_le_bytes());
+            bmp_32[28] = 32;
+            bmp_32.extend_from_slice(&[
+                0, 0, 255, 10, 0, 255, 0, 20, 255, 0, 0, 30, 255, 255, 255, 40,
+            ]);
+            let img: Image = decode_bmp(&bmp_32).unwrap();
+            let alphas: Vec<u8> = img.pixels.iter().map(|p| p.a).collect();
+            assert_eq!(alphas, vec![10, 20, 30, 40]);
+            assert!(img
+                .pixels
+                .iter()
+                .zip(expected.iter())
+                .all(|(a, b)| a.equals_rgb(b)));
+
+            assert_eq!(decode_bmp(&BMP_24[..60]).err(), Some(ImgError::DataError));
+            assert_eq!(decode_bmp(&BMP_24[1..]).err(), Some(ImgError::HeaderError));
+
+            //OS/2 BITMAPCOREHEADER and an info header running past the end of the file
+            for info_len in [12u32, 124] {
+                let mut bmp: Vec<u8> = BMP_24.to_vec();
+                bmp[14..18].copy_from_slice(&info_len.to_le_bytes());
+                assert_eq!(decode_bmp(&bmp).err(), Some(ImgError::HeaderError));
+            }
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn write_bmp_test() -> io::Result<()> {
+            let pixels: Vec<Pixel> = (0..6)
+                .map(|i| Pixel::new(i, 10 * i, 20 * i, 100 + i))
+                .collect();
+            for channels in [3, 4] {
+                let img: Image = Image::from_pixels(pixels.clone(), 3, 2, channels, 0).unwrap();
+                let path: PathBuf =
+                    std::env::temp_dir().join(format!("qoi_write_bmp_test{channels}.bmp"));
+                img.write_bmp(&path)?;
+                let bytes: Vec<u8> = read(&path)?;
+                remove_file(&path)?;
+
+                let decoded: Image = decode_bmp(&bytes).unwrap();
+                assert_eq!((decoded.width, decoded.height), (3, 2));
+                for (dec, pix) in decoded.pixels.iter().zip(img.pixels.iter()) {
+                    let alpha: u8 = if channels == 3 { 255 } else { pix.a };
+                    assert_eq!(*dec, Pixel { a: alpha, ..*pix });
+                }
+            }
+            Ok(())
+        }
+
+        #[cfg(feature = "rayon")]
+        #[test]
+        fn parallel_roundtrip_test() {
+            let pixels: Vec<Pixel> = (0..7 * 10)
+                .map(|i| Pixel::new(i as u8, (i / 7) as u8 * 20, 3, 255 - i as u8))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 7, 10, 4, 1).unwrap();
+
+            for tiles in [0, 1, 3, 4, 10, 25] {
+                let bytes: Vec<u8> = encode_parallel(&img, tiles);
+                let decoded: Image = decode_parallel(&bytes).unwrap();
+                assert_eq!((decoded.width, decoded.height), (7, 10));
+                assert_eq!((decoded.channels, decoded.colorspace), (4, 1));
+                assert_eq!(decoded.pixels, img.pixels);
+            }
+
+            for (width, height) in [(7, 0), (0, 5), (0, 0)] {
+                let empty: Image = Image::blank(width, height, 3, 0).unwrap();
+                let decoded: Image = decode_parallel(&encode_parallel(&empty, 3)).unwrap();
+                assert!(decoded == empty);
+            }
+
+            let bytes: Vec<u8> = encode_parallel(&img, 3);
+            assert_eq!(
+                decode_parallel(&bytes[..bytes.len() - 1]).err(),
+                Some(ImgError::DecodeError)
+            );
+            assert_eq!(
+                decode_parallel(&encode_from_image(&img)).err(),
+                Some(ImgError::HeaderError)
+            );
+        }
+
+        #[test]
+        fn extract_frames_test() {
+            //6x2 sheet, every pixel tagged with its own column
+            let mut pixels: Vec<Pixel> = Vec::new();
+            for y in 0..2 {
+                for x in 0..6 {
+                    pixels.push(Pixel::new(x, y, 0, 255));
+                }
+            }
+            let sheet: Image = Image::from_pixels(pixels, 6, 2, 4, 0).unwrap();
+            let frames: Vec<Image> = sheet.extract_frames(2).unwrap();
+
+            assert_eq!(frames.len(), 3);
+            for (i, frame) in frames.iter().enumerate() {
+                let x: u8 = i as u8 * 2;
+                assert_eq!((frame.width, frame.height), (2, 2));
+                assert_eq!(
+                    frame.pixels,
+                    vec![
+                        Pixel::new(x, 0, 0, 255),
+                        Pixel::new(x + 1, 0, 0, 255),
+                        Pixel::new(x, 1, 0, 255),
+                        Pixel::new(x + 1, 1, 0, 255),
+                    ]
+                );
+            }
+            assert!(sheet.extract_frames(4).is_err());
+            assert!(sheet.extract_frames(0).is_err());
+        }
+
+        #[test]
+        fn packed_u32_test() {
+            let pix: Pixel = Pixel::new(0x12, 0x34, 0x56, 0x78);
+            assert_eq!(pix.to_u32_rgba(), 0x1234_5678);
+            assert_eq!(pix.to_u32_bgra(), 0x5634_1278);
+            assert_eq!(pix.to_u32_rgba().to_be_bytes(), [0x12, 0x34, 0x56, 0x78]);
+            assert_eq!(Pixel::from_u32_rgba(0x1234_5678), pix);
+            assert_eq!(Pixel::from_u32_bgra(0x5634_1278), pix);
+
+            for pix in [
+                Pixel::new(0, 0, 0, 0),
+                Pixel::new(255, 255, 255, 255),
+                Pixel::new(255, 0, 128, 1),
+            ] {
+                assert_eq!(Pixel::from_u32_rgba(pix.to_u32_rgba()), pix);
+                assert_eq!(Pixel::from_u32_bgra(pix.to_u32_bgra()), pix);
+                assert_eq!(Pixel::from_u32(pix.to_u32()), pix);
+            }
+            assert_eq!(pix.to_u32(), 0x1234_5678);
+            assert_eq!(Pixel::from_u32(0xFF00_80FF), Pixel::new(255, 0, 128, 255));
+        }
+
+        #[test]
+        fn hex_test() {
+            let pix: Pixel = Pixel::new(0x12, 0xab, 0x00, 0xff);
+            assert_eq!(Pixel::from_hex("#12ab00"), Ok(pix));
+            assert_eq!(Pixel::from_hex("12AB00ff"), Ok(pix));
+            assert_eq!(
+                Pixel::from_hex("#12ab0080"),
+                Ok(Pixel::new(0x12, 0xab, 0, 0x80))
+            );
+            assert_eq!(pix.to_hex(), "#12ab00ff");
+            assert_eq!(Pixel::from_hex(&pix.to_hex()), Ok(pix));
+            for bad in [
+                "",
+                "#",
+                "#12ab0",
+                "#12ab00f",
+                "12ab00ff00",
+                "#12ab0g",
+                "+12ab00",
+                "##12ab00",
+                "#12ab00 ",
+            ] {
+                assert_eq!(Pixel::from_hex(bad), Err(ImgError::DataError), "{bad:?}");
+            }
+        }
+
+        #[test]
+        fn pixel_arithmetic_test() {
+            let a: Pixel = Pixel::new(200, 50, 0, 255);
+            let b: Pixel = Pixel::new(100, 100, 0, 1);
+            assert_eq!(a + b, Pixel::new(255, 150, 0, 255));
+            assert_eq!(a - b, Pixel::new(100, 0, 0, 254));
+            assert_eq!(b - a, Pixel::new(0, 50, 0, 0));
+            assert_eq!(a.saturating_add(&b), a + b);
+            assert_eq!(a.saturating_sub(&b), a - b);
+        }
+
+        #[test]
+        fn index_slot_pressure_test() {
+            //both colours hash into slot 53 and keep evicting each other
+            let a: Pixel = Pixel::new(0, 0, 0, 255);
+            let b: Pixel = Pixel::new(64, 0, 0, 255);
+            assert_eq!(color_hash(&a), color_hash(&b));
+
+            let pixels: Vec<Pixel> = [b, a].repeat(8);
+            let img: Image = Image::from_pixels(pixels, 4, 4, 4, 0).unwrap();
+            let pressure: [u32; 64] = index_slot_pressure(&img);
+
+            assert_eq!(pressure[53], 2);
+            assert_eq!(pressure.iter().sum::<u32>(), 2);
+        }
+
+        //Writer that fails once more than `limit` bytes have been written to it
+        #[cfg(feature = "std")]
+        struct FailingWriter {
+            written: usize,
+            limit: usize,
+        }
+
+        #[cfg(feature = "std")]
+        impl Write for FailingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.written + buf.len() > self.limit {
+                    return Err(io::Error::new(io::ErrorKind::WriteZero, "writer is full"));
+                }
+                self.written += buf.len();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn encode_to_writer_test() -> io::Result<()> {
+            //noise followed by a long trailing run, so both the block flushing and the final run flush are exercised
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(256 * 256);
+            let mut state: u32 = 0x1234_5678;
+            for _ in 0..256 * 192 {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                pixels.push(Pixel::from_u32_rgba(state));
+            }
+            pixels.resize(256 * 256, Pixel::new(1, 2, 3, 255));
+            let img: Image = Image::from_pixels(pixels, 256, 256, 4, 0).unwrap();
+
+            let mut streamed: Vec<u8> = Vec::new();
+            encode_to_writer(&img, &mut streamed)?;
+            assert!(streamed.len() > STREAM_BUFFER_SIZE);
+
+            let decoded: Image = decode(streamed.clone()).unwrap();
+            assert_eq!(decoded.pixels, img.pixels);
+            assert_eq!(streamed, encode_from_image(&img));
+            Ok(())
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn encode_to_writer_error_test() {
+            let img: Image =
+                Image::from_pixels(vec![Pixel::new(9, 9, 9, 255); 16], 4, 4, 4, 0).unwrap();
+            let mut writer: FailingWriter = FailingWriter {
+                written: 0,
+                limit: 10,
+            };
+            let result = encode_to_writer(&img, &mut writer);
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::WriteZero);
+        }
+
+        #[test]
+        #[cfg(feature = "mmap")]
+        fn decode_mmap_test() {
+            let dir: PathBuf = std::env::temp_dir().join("qoi_decode_mmap_test");
+            create_dir_all(&dir).unwrap();
+            let img: Image = Image::from_pixels(
+                (0..48u8)
+                    .map(|i| Pixel::new(i, 255 - i, i / 2, 255))
+                    .collect(),
+                8,
+                6,
+                3,
+                0,
+            )
+            .unwrap();
+            let path: PathBuf = dir.join("img.qoi");
+            std::fs::write(&path, encode_from_image(&img)).unwrap();
+            assert!(decode_mmap(&path).unwrap() == img);
+
+            for truncated in [&b""[..], b"qoif"] {
+                std::fs::write(&path, truncated).unwrap();
+                assert_eq!(decode_mmap(&path).err(), Some(ImgError::HeaderError));
+            }
+            assert_eq!(
+                decode_mmap(dir.join("missing.qoi")).err(),
+                Some(ImgError::IoError(io::ErrorKind::NotFound))
+            );
+            remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn decode_from_reader_test() {
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(32 * 16);
+            for i in 0..32 * 16 {
+                pixels.push(Pixel::new((i % 7) as u8 * 30, (i / 32) as u8, 200, 255 - (i % 3) as u8));
+            }
+            let img: Image = Image::from_pixels(pixels, 32, 16, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
+
+            let streamed: Image = decode_from_reader(&mut io::Cursor::new(&bytes)).unwrap();
+            let decoded: Image = decode(bytes.clone()).unwrap();
+            assert_eq!(streamed.pixels, img.pixels);
+            assert_eq!(streamed.pixels, decoded.pixels);
+            assert_eq!((streamed.width, streamed.height), (32, 16));
+
+            //cut off in the middle of the chunks and inside the end marker
+            for cut in [10, 20, bytes.len() - 12, bytes.len() - 1] {
+                let result = decode_from_reader(&mut io::Cursor::new(&bytes[..cut]));
+                assert!(result.is_err());
+            }
+            let mut bad_end: Vec<u8> = bytes.clone();
+            let last: usize = bad_end.len() - 1;
+            bad_end[last] = 2;
+            assert_eq!(
+                decode_from_reader(&mut io::Cursor::new(bad_end)).err(),
+                Some(ImgError::DecodeError)
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn write_raw_test() -> io::Result<()> {
+            let pixels: Vec<Pixel> = (0..40 * 30)
+                .map(|i| Pixel::new(i as u8, (i >> 8) as u8, 7, 255 - i as u8))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 40, 30, 4, 0).unwrap();
+
+            for channels in [3, 4] {
+                let mut cursor: io::Cursor<Vec<u8>> = io::Cursor::new(Vec::new());
+                img.write_raw(&mut cursor, channels)?;
+                let expected: Vec<u8> = img.to_bytes_with_channels(channels).unwrap();
+                assert_eq!(expected.len(), 40 * 30 * channels as usize);
+                assert_eq!(cursor.into_inner(), expected);
+            }
+            assert_eq!(img.to_bytes_with_channels(4).unwrap(), img.to_bytes_rgba());
+
+            let result = img.write_raw(&mut io::sink(), 2);
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+            Ok(())
+        }
+
+        #[test]
+        fn rgb_image_test() {
+            let data: Vec<u8> = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+            let img: Image = Image::new(data, 2, 2, 3, 0).unwrap();
+            assert_eq!(
+                img.pixels,
+                vec![
+                    Pixel::new(1, 2, 3, 255),
+                    Pixel::new(4, 5, 6, 255),
+                    Pixel::new(7, 8, 9, 255),
+                    Pixel::new(10, 11, 12, 255),
+                ]
+            );
+            //divisible by 4 but not by 3
+            let err: ImgError = Image::new(vec![0; 16], 2, 2, 3, 0).err().unwrap();
+            assert_eq!(
+                err,
+                ImgError::ByteCountError {
+                    expected_multiple: 3,
+                    got: 16
+                }
+            );
+            assert_eq!(
+                err.to_string(),
+                "invalid number of bytes: 16 is not divisible by 3"
+            );
+            assert_eq!(
+                Image::new(vec![0; 15], 2, 2, 4, 0)
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                "invalid number of bytes: 15 is not divisible by 4"
+            );
+        }
+
+        #[cfg(feature = "simd")]
+        #[test]
+        fn simd_diff_test() {
+            //alternating prev/curr pixels make consecutive deltas cover every (curr, prev) byte pair in each channel
+            for prev in 0..=255u8 {
+                let pixels: Vec<Pixel> = (0..=255u8)
+                    .flat_map(|v| {
+                        [
+                            Pixel::new(prev, prev ^ 0x55, !prev, 255),
+                            Pixel::new(v, v ^ 0x55, !v, 255),
+                        ]
+                    })
+                    .collect();
+                let mut first_prev: Pixel = Pixel::new(0, 0, 0, 255);
+                for batch in pixels.chunks(simd::BATCH) {
+                    let mut diffs: [(i16, i16, i16); simd::BATCH] = [(0, 0, 0); simd::BATCH];
+                    simd::batch_diff(batch, first_prev, &mut diffs);
+                    for (k, pixel) in batch.iter().enumerate() {
+                        let prev_pixel: Pixel = if k == 0 { first_prev } else { batch[k - 1] };
+                        assert_eq!(diffs[k], pixel.diff(&prev_pixel));
+                    }
+                    first_prev = batch[batch.len() - 1];
+                }
+            }
+        }
+
+        #[cfg(feature = "simd")]
+        #[test]
+        fn simd_identical_test() {
+            let chunks = |img: &Image, batched: bool| {
+                let mut out: Vec<u8> = Vec::new();
+                walk_chunks(img, batched, |chunk, data, pixel, run| {
+                    push_chunk(&mut out, chunk, data, pixel, run);
+                    Ok::<(), ()>(())
+                })
+                .unwrap();
+                out
+            };
+            //sizes around the batch length, gradients with wrap-around and runs
+            for (w, h) in [(1, 1), (7, 9), (8, 8), (13, 5), (64, 3), (65, 2)] {
+                for channels in [3, 4] {
+                    let pixels: Vec<Pixel> = (0..w * h)
+                        .map(|i: u32| {
+                            let n: u32 = i.wrapping_mul(2654435761) >> 24;
+                            Pixel::new(
+                                (i * 3) as u8,
+                                (n % 5) as u8 * 60,
+                                (i / 4) as u8,
+                                255 - (i / 7 % 2) as u8,
+                            )
+                        })
+                        .collect();
+                    let img: Image = Image::from_pixels(pixels, w, h, channels, 0).unwrap();
+                    let scalar: Vec<u8> = chunks(&img, false);
+                    assert_eq!(chunks(&img, true), scalar);
+                    let decoded: Image = decode(encode_from_image(&img)).unwrap();
+                    assert_eq!(decoded.pixels.len(), img.pixels.len());
+                }
+            }
+        }
+
+        #[test]
+        fn decode_into_test() {
+            let pixels: Vec<Pixel> = (0..15)
+                .map(|i| Pixel::new(i * 17, 3, 200 - i, 128 + i))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 5, 3, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
+
+            let mut out: Vec<u8> = vec![0xAA; 15 * 4 + 2];
+            assert_eq!(decode_into(&bytes, &mut out), Ok((5, 3)));
+            assert_eq!(out[..60], img.to_bytes_rgba()[..]);
+            assert_eq!(out[60..], [0xAA, 0xAA]);
+
+            let mut small: Vec<u8> = vec![0; 15 * 4 - 1];
+            assert_eq!(decode_into(&bytes, &mut small), Err(ImgError::DataError));
+            assert_eq!(
+                decode_into(&bytes[..bytes.len() - 9], &mut out),
+                Err(ImgError::DecodeError)
+            );
+        }
+
+        #[test]
+        fn long_run_test() {
+            for n in [62u32, 63, 124, 125, 10000] {
+                let img: Image =
+                    Image::from_pixels(vec![Pixel::new(200, 10, 90, 255); n as usize], n, 1, 4, 0)
+                        .unwrap();
+                let bytes: Vec<u8> = encode_from_image(&img);
+                //one RGB chunk, then runs of 62 for the remaining pixels
+                let n_runs: usize = (n as usize - 1).div_ceil(62);
+                assert_eq!(bytes.len(), 14 + 4 + n_runs + 8);
+                let decoded: Image = decode(bytes).unwrap();
+                assert_eq!(decoded.pixels.len(), n as usize);
+                assert_eq!(decoded.pixels, img.pixels);
+            }
+        }
+
+        #[test]
+        fn non_square_dimensions_test() {
+            let (width, height): (u32, u32) = (640, 480);
+            let pixels: Vec<Pixel> = (0..height)
+                .flat_map(|y| {
+                    (0..width).map(move |x| Pixel::new(x as u8, y as u8, (x / 3) as u8, 255))
+                })
+                .collect();
+            let img: Image = Image::from_pixels(pixels, width, height, 4, 0).unwrap();
+            assert_eq!((img.width(), img.height()), (640, 480));
+
+            let bytes: Vec<u8> = encode_from_image(&img);
+            let header: QoiHeader = decode_header(&bytes).unwrap();
+            assert_eq!((header.width, header.height), (640, 480));
+            let decoded: Image = decode(bytes).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (640, 480));
+            assert_eq!(
+                decoded.get_pixel(639, 0),
+                Some(Pixel::new(127, 0, 213, 255))
+            );
+            assert_eq!(decoded.get_pixel(0, 479), Some(Pixel::new(0, 223, 0, 255)));
+            assert!(decoded == img);
+
+            let rgba: Vec<u8> = img.to_bytes_rgba();
+            let from_bytes: Image = Image::new(rgba, width, height, 4, 0).unwrap();
+            assert!(from_bytes == img);
+        }
+
+        #[test]
+        fn invalid_format_test() {
+            for (channels, colorspace) in [(0, 0), (2, 0), (5, 0), (255, 1), (3, 2), (4, 255)] {
+                let err: ImgError = ImgError::InvalidFormat {
+                    channels,
+                    colorspace,
+                };
+                assert_eq!(
+                    Image::new(vec![0; 12], 1, 1, channels, colorspace).err(),
+                    Some(err.clone())
+                );
+                assert_eq!(
+                    Image::from_pixels(vec![Pixel::new(0, 0, 0, 255)], 1, 1, channels, colorspace)
+                        .err(),
+                    Some(err)
+                );
+            }
+            for (channels, colorspace) in [(3, 0), (3, 1), (4, 0), (4, 1)] {
+                let data: Vec<u8> = vec![0; channels as usize];
+                assert!(Image::new(data, 1, 1, channels, colorspace).is_ok());
+            }
+
+            //a stream whose header declares 2 channels is rejected when decoding
+            let mut bytes: Vec<u8> =
+                encode_from_image(&Image::solid(2, 2, Pixel::new(1, 2, 3, 255), 4, 0).unwrap());
+            bytes[12] = 2;
+            assert_eq!(
+                decode(bytes).err(),
+                Some(ImgError::InvalidFormat {
+                    channels: 2,
+                    colorspace: 0
+                })
+            );
+        }
+
+        #[test]
+        fn builder_test() {
+            let rgba: Vec<u8> = (0..4 * 2 * 4).map(|i| i as u8).collect();
+            let img: Image = Image::builder()
+                .height(2)
+                .width(4)
+                .colorspace(1)
+                .rgba_bytes(rgba.clone())
+                .build()
+                .unwrap();
+            assert_eq!(
+                (img.width, img.height, img.channels, img.colorspace),
+                (4, 2, 4, 1)
+            );
+            assert_eq!(img.to_bytes_rgba(), rgba);
+            assert_eq!(img.get_pixel(3, 0), Some(Pixel::new(12, 13, 14, 15)));
+
+            let pixels: Vec<Pixel> = vec![Pixel::new(1, 2, 3, 255); 6];
+            let rgb: Image = Image::builder()
+                .width(3)
+                .height(2)
+                .channels(3)
+                .pixels(pixels.clone())
+                .build()
+                .unwrap();
+            assert!(rgb == Image::from_pixels(pixels.clone(), 3, 2, 3, 0).unwrap());
+
+            let valid: ImageBuilder = Image::builder().width(3).height(2).pixels(pixels);
+            assert!(valid.clone().build().is_ok());
+            assert!(matches!(
+                valid.clone().width(2).build(),
+                Err(ImgError::PixelNumberError)
+            ));
+            assert!(matches!(
+                valid.clone().channels(2).build(),
+                Err(ImgError::InvalidFormat { .. })
+            ));
+            assert!(matches!(
+                valid.clone().colorspace(2).build(),
+                Err(ImgError::InvalidFormat { .. })
+            ));
+            assert!(matches!(
+                valid.clone().rgba_bytes(vec![0; 23]).build(),
+                Err(ImgError::ByteCountError { .. })
+            ));
+            assert!(matches!(
+                Image::builder().width(3).height(2).build(),
+                Err(ImgError::DataError)
+            ));
+        }
+
+        #[test]
+        fn solid_image_run_test() {
+            //transparent black is found in the zeroed index, any other colour needs an RGB chunk first
+            for (color, first_len) in [
+                (Pixel::new(0, 0, 0, 0), 1),
+                (Pixel::new(200, 10, 90, 255), 4),
+            ] {
+                let img: Image = Image::solid(1000, 1000, color, 4, 0).unwrap();
+                let bytes: Vec<u8> = encode_from_image(&img);
+                let n_runs: usize = 999_999usize.div_ceil(62);
+                assert_eq!(bytes.len(), 14 + first_len + n_runs + 8);
+                let histogram: ChunkHistogram = analyze(&bytes).unwrap();
+                assert_eq!(
+                    (histogram.run.chunks, histogram.run.pixels),
+                    (n_runs, 999_999)
+                );
+
+                let decoded: Image = decode(bytes).unwrap();
+                assert_eq!((decoded.width, decoded.height), (1000, 1000));
+                assert_eq!(decoded.pixels.len(), 1_000_000);
+                assert!(decoded.pixels.iter().all(|pixel| *pixel == color));
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn serde_test() {
+            let pixels: Vec<Pixel> = vec![Pixel::new(1, 2, 3, 4), Pixel::new(5, 6, 7, 8)];
+            let img: Image = Image::from_pixels(pixels, 2, 1, 4, 1).unwrap();
+            let json: String = serde_json::to_string(&img).unwrap();
+            assert_eq!(
+                json,
+                r#"{"width":2,"height":1,"channels":4,"colorspace":1,"pixels":[{"r":1,"g":2,"b":3,"a":4},{"r":5,"g":6,"b":7,"a":8}]}"#
+            );
+            let back: Image = serde_json::from_str(&json).unwrap();
+            assert_eq!((back.width, back.height), (2, 1));
+            assert_eq!((back.channels, back.colorspace), (4, 1));
+            assert_eq!(back.pixels, img.pixels);
+
+            let short: String = json
+                .replace(r#"{"r":5,"g":6,"b":7,"a":8}"#, "")
+                .replace(",]", "]");
+            assert!(serde_json::from_str::<Image>(&short).is_err());
+        }
+
+        #[test]
+        fn clone_test() {
+            let img: Image =
+                Image::from_pixels(vec![Pixel::new(1, 2, 3, 255); 6], 3, 2, 4, 0).unwrap();
+            let mut copy: Image = img.clone();
+            assert!(copy == img);
+            copy.set_pixel(2, 1, Pixel::new(9, 9, 9, 9)).unwrap();
+            assert!(copy != img);
+            assert_eq!(img.get_pixel(2, 1), Some(Pixel::new(1, 2, 3, 255)));
+            assert!(decode(encode_from_image(&img)).unwrap() == img);
+        }
+
+        #[test]
+        fn sequence_test() {
+            let frames: Vec<Image> = (0..3u8)
+                .map(|i| {
+                    let pixels: Vec<Pixel> = (0..12u8)
+                        .map(|p| Pixel::new(p * 20, i * 80, 255 - p, 255))
+                        .collect();
+                    Image::from_pixels(pixels, 4, 3, 4, 0).unwrap()
+                })
+                .collect();
+            let bytes: Vec<u8> = encode_sequence(&frames, 24);
+            assert!(bytes[0..4] == SEQUENCE_MAGIC);
+            let (decoded, fps) = decode_sequence(&bytes).unwrap();
+            assert_eq!(fps, 24);
+            assert!(decoded == frames);
+
+            assert!(matches!(
+                decode_sequence(&encode_from_image(&frames[0])),
+                Err(ImgError::HeaderError)
+            ));
+            assert!(matches!(
+                decode_sequence(&bytes[..bytes.len() - 1]),
+                Err(ImgError::DecodeError)
+            ));
+        }
+
+        #[test]
+        fn sequence_delta_test() {
+            //a static background where only the top left corner changes
+            let frames: Vec<Image> = (0..7u8)
+                .map(|i| {
+                    let mut img: Image =
+                        Image::solid(8, 6, Pixel::new(30, 60, 90, 255), 4, 0).unwrap();
+                    img.set_pixel(0, 0, Pixel::new(i * 40, 255 - i, i, 200))
+                        .unwrap();
+                    img.set_pixel(1, 0, Pixel::new(5, i * 30, 250, 255))
+                        .unwrap();
+                    img
+                })
+                .collect();
+            let keyframes: Vec<u8> = encode_sequence(&frames, 30);
+            let deltas: Vec<u8> = encode_sequence_with_keyframes(&frames, 30, 3);
+            assert!(deltas.len() < keyframes.len());
+            let (decoded, fps) = decode_sequence(&deltas).unwrap();
+            assert_eq!(fps, 30);
+            assert!(decoded == frames);
+
+            //3-channel frames lose their alpha, and a change of dimensions forces a keyframe
+            let mut rgb: Image = Image::solid(8, 6, Pixel::new(1, 2, 3, 7), 3, 1).unwrap();
+            let mirrored: Vec<Image> = vec![
+                rgb.clone(),
+                {
+                    rgb.set_pixel(3, 3, Pixel::new(9, 9, 9, 9)).unwrap();
+                    rgb.clone()
+                },
+                Image::solid(2, 2, Pixel::new(4, 5, 6, 255), 4, 0).unwrap(),
+                Image::solid(2, 2, Pixel::new(4, 5, 7, 255), 4, 0).unwrap(),
+            ];
+            let keyframes: Vec<u8> = encode_sequence(&mirrored, 1);
+            let bytes: Vec<u8> = encode_sequence_with_keyframes(&mirrored, 1, 10);
+            let (decoded, _) = decode_sequence(&bytes).unwrap();
+            assert!(decoded == decode_sequence(&keyframes).unwrap().0);
+            for (frame, original) in decoded.iter().zip(&mirrored) {
+                assert_eq!(
+                    (frame.width, frame.height, frame.channels, frame.colorspace),
+                    (
+                        original.width,
+                        original.height,
+                        original.channels,
+                        original.colorspace
+                    )
+                );
+                for (p, q) in frame.pixels.iter().zip(&original.pixels) {
+                    assert_eq!((p.r, p.g, p.b), (q.r, q.g, q.b));
+                    assert_eq!(p.a, if original.channels == 3 { 255 } else { q.a });
+                }
+            }
+
+            //a delta frame without a previous frame
+            let mut orphan: Vec<u8> = encode_sequence(&frames[..1], 30);
+            orphan[10] = 1;
+            assert!(matches!(
+                decode_sequence(&orphan),
+                Err(ImgError::DecodeError)
+            ));
+        }
+
+        #[test]
+        fn encode_raw_test() {
+            let mut rng: u32 = 0x5EED;
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(12 * 9);
+            for i in 0..12 * 9 {
+                rng ^= rng << 13;
+                rng ^= rng >> 17;
+                rng ^= rng << 5;
+                //every third pixel repeats the previous one so that runs and index hits occur in the regular encoding
+                match pixels.last() {
+                    Some(prev) if i % 3 == 0 => pixels.push(*prev),
+                    _ => pixels.push(Pixel::from_u32(rng)),
+                }
+            }
+            for channels in [3, 4] {
+                let img: Image = Image::from_pixels(pixels.clone(), 12, 9, channels, 1).unwrap();
+                let raw: Vec<u8> = encode_raw(&img);
+                assert_eq!(raw.len(), 14 + 5 * 12 * 9 + 8);
+                assert_eq!(raw[..14], encode_from_image(&img)[..14]);
+                assert_eq!(analyze(&raw).unwrap().rgba.chunks, 12 * 9);
+                assert!(decode_slice(&raw).unwrap() == decode(encode_from_image(&img)).unwrap());
+            }
+            assert_eq!(encode_raw(&Image::blank(0, 0, 4, 0).unwrap()).len(), 22);
+        }
+
+        #[test]
+        fn rgba_bytes_test() {
+            let rgba: Vec<u8> = (0..6 * 4 * 4).map(|i| (i * 37 % 256) as u8).collect();
+            let bytes: Vec<u8> = encode_rgba(&rgba, 6, 4);
+            let img: Image = Image::new(rgba.clone(), 6, 4, 4, 0).unwrap();
+            assert!(bytes == encode_from_image(&img));
+            assert_eq!(decode_to_rgba(&bytes).unwrap(), (rgba, 6, 4));
+
+            let rgb: Image = Image::solid(2, 3, Pixel::new(1, 2, 3, 4), 3, 0).unwrap();
+            let (decoded, w, h) = decode_to_rgba(&encode_from_image(&rgb)).unwrap();
+            assert_eq!((w, h), (2, 3));
+            assert!(decoded.chunks_exact(4).all(|p| p == [1, 2, 3, 255]));
+            assert!(decode_to_rgba(&bytes[..10]).is_err());
+        }
+
+        #[test]
+        #[should_panic]
+        fn encode_rgba_length_test() {
+            encode_rgba(&[0; 15], 2, 2);
+        }
+
+        #[test]
+        fn encode_stats_test() {
+            //one RGB chunk, a run of 5, a diff, then an index hit on the first colour
+            let a: Pixel = Pixel::new(200, 10, 90, 255);
+            let pixels: Vec<Pixel> = vec![a, a, a, a, a, a, Pixel::new(201, 10, 90, 255), a];
+            let img: Image = Image::from_pixels(pixels, 4, 2, 4, 0).unwrap();
+            let (bytes, stats) = encode_with_stats(&img);
+            assert!(bytes == encode_from_image(&img));
+            assert_eq!(stats.pixels, 8);
+            assert_eq!(stats.bytes, bytes.len());
+            assert_eq!(stats.ratio, bytes.len() as f64 / 32.0);
+            assert_eq!(
+                (
+                    stats.run,
+                    stats.index,
+                    stats.luma,
+                    stats.diff,
+                    stats.rgb,
+                    stats.rgba
+                ),
+                (1, 1, 0, 1, 1, 0)
+            );
+        }
+
+        #[test]
+        fn analyze_test() {
+            let a: Pixel = Pixel::new(200, 10, 90, 255);
+            let pixels: Vec<Pixel> = vec![a, a, a, a, a, a, Pixel::new(201, 10, 90, 128), a];
+            let img: Image = Image::from_pixels(pixels, 4, 2, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
+            let histogram: ChunkHistogram = analyze(&bytes).unwrap();
+
+            let tally = |chunks: usize, pixels: usize, bytes: usize| ChunkTally {
+                chunks,
+                pixels,
+                bytes,
+            };
+            assert_eq!(histogram.rgb, tally(1, 1, 4));
+            assert_eq!(histogram.run, tally(1, 5, 1));
+            assert_eq!(histogram.rgba, tally(1, 1, 5));
+            assert_eq!(histogram.index, tally(1, 1, 1));
+            assert_eq!(histogram.luma, tally(0, 0, 0));
+            assert_eq!(histogram.diff, tally(0, 0, 0));
+            assert_eq!(bytes.len(), 22 + 4 + 1 + 5 + 1);
+
+            assert_eq!(
+                analyze(&bytes[..bytes.len() - 1]),
+                Err(ImgError::DecodeError)
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "std")]
+        fn colorspace_test() {
+            //sRGB 0.5 is 0.2140 linear, sRGB 128/255 is 0.2159 linear (55.04 in 8 bits)
+            assert!((srgb_to_linear(0.5) - 0.214041).abs() < 1e-6);
+            assert!((srgb_to_linear(128.0 / 255.0) - 0.215861).abs() < 1e-6);
+            assert!((linear_to_srgb(0.214041) - 0.5).abs() < 1e-6);
+            for v in 0..=255 {
+                let c: f64 = v as f64 / 255.0;
+                assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-9);
+            }
+
+            let pixels: Vec<Pixel> = vec![Pixel::new(128, 0, 255, 77), Pixel::new(10, 188, 1, 255)];
+            let img: Image = Image::from_pixels(pixels, 2, 1, 4, 0).unwrap();
+            let linear: Image = img.to_linear();
+            assert_eq!(linear.colorspace, 1);
+            assert_eq!(linear.pixels[0], Pixel::new(55, 0, 255, 77));
+            assert_eq!(linear.pixels[1], Pixel::new(1, 128, 0, 255));
+            assert!(linear.to_linear() == linear);
+            assert!(img.to_srgb() == img);
+
+            let srgb: Image = linear.to_srgb();
+            assert_eq!(srgb.colorspace, 0);
+            assert_eq!(srgb.pixels[0], Pixel::new(128, 0, 255, 77));
+            assert_eq!(srgb.pixels[1].g, 188);
+        }
+
+        #[test]
+        fn brightness_contrast_test() {
+            let pixels: Vec<Pixel> = (0..=255u8)
+                .map(|c| Pixel::new(c, 255 - c, c / 2, c))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 16, 16, 4, 0).unwrap();
+
+            let bright: Image = img.adjust_brightness(300);
+            assert!(bright
+                .pixels
+                .iter()
+                .all(|p| (p.r, p.g, p.b) == (255, 255, 255)));
+            assert!(bright
+                .pixels
+                .iter()
+                .zip(&img.pixels)
+                .all(|(p, q)| p.a == q.a));
+            let dark: Image = img.adjust_brightness(-10);
+            assert_eq!(dark.pixels[5], Pixel::new(0, 240, 0, 5));
+            assert!(img
+                .adjust_brightness(i16::MIN)
+                .pixels
+                .iter()
+                .all(|p| (p.r, p.g, p.b) == (0, 0, 0)));
+
+            assert!(img.adjust_contrast(1.0).pixels == img.pixels);
+            let flat: Image = img.adjust_contrast(0.0);
+            assert!(flat
+                .pixels
+                .iter()
+                .all(|p| (p.r, p.g, p.b) == (128, 128, 128)));
+            let steep: Image = img.adjust_contrast(2.0);
+            assert_eq!(steep.pixels[100], Pixel::new(72, 182, 0, 100));
+            assert_eq!((steep.width, steep.height, steep.channels), (16, 16, 4));
+        }
+
+        #[test]
+        fn quantize_test() {
+            let black: Pixel = Pixel::new(0, 0, 0, 255);
+            let red: Pixel = Pixel::new(255, 0, 0, 128);
+            let pixels: Vec<Pixel> = (0..12)
+                .map(|i| if i % 3 == 0 { red } else { black })
+                .collect();
+            let img: Image = Image::from_pixels(pixels.clone(), 4, 3, 4, 0).unwrap();
+
+            let (palette, indices) = img.quantize(2);
+            assert_eq!(palette.len(), 2);
+            assert!(palette.contains(&black) && palette.contains(&red));
+            assert_eq!(indices.len(), 12);
+            assert!(indices
+                .iter()
+                .zip(&pixels)
+                .all(|(&i, p)| palette[i as usize] == *p));
+            assert_eq!(img.quantize(2), (palette.clone(), indices.clone()));
+            //more room than colours changes nothing
+            assert_eq!(img.quantize(16), (palette, indices));
+
+            //a single colour averages everything
+            let (palette, indices) = img.quantize(1);
+            assert_eq!(palette, vec![Pixel::new(85, 0, 0, 213)]);
+            assert!(indices.iter().all(|&i| i == 0));
+            assert_eq!(img.quantize(0).0.len(), 1);
+
+            //a gradient of 1024 colours never exceeds the limit, and every index is in range
+            let pixels: Vec<Pixel> = (0..1024u32)
+                .map(|i| Pixel::new(i as u8, (i >> 2) as u8, (i * 7) as u8, 255))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 32, 32, 3, 0).unwrap();
+            for max_colors in [2usize, 16, 256, 1000] {
+                let (palette, indices) = img.quantize(max_colors);
+                assert_eq!(palette.len(), max_colors.min(256));
+                assert!(indices.iter().all(|&i| (i as usize) < palette.len()));
+            }
+            assert_eq!(
+                Image::blank(0, 0, 4, 0).unwrap().quantize(8),
+                (Vec::new(), Vec::new())
+            );
+        }
+
+        #[test]
+        fn dominant_colors_test() {
+            let red: Pixel = Pixel::new(255, 0, 0, 255);
+            let blue: Pixel = Pixel::new(0, 0, 255, 255);
+            let pixels: Vec<Pixel> = (0..64)
+                .map(|i| if i % 4 == 3 { blue } else { red })
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 8, 8, 3, 0).unwrap();
+
+            let colors: Vec<(Pixel, f32)> = img.dominant_colors(2);
+            assert_eq!(colors.len(), 2);
+            assert_eq!(colors[0].0, red);
+            assert!((colors[0].1 - 0.75).abs() < 1e-6);
+            assert_eq!(colors[1].0, blue);
+            assert!((colors[1].1 - 0.25).abs() < 1e-6);
+            assert!(img.dominant_colors(10) == colors);
+
+            //fewer clusters than colours merge them, and the coverage always adds up
+            let colors: Vec<(Pixel, f32)> = img.dominant_colors(1);
+            assert_eq!(colors, vec![(Pixel::new(191, 0, 64, 255), 1.0)]);
+            let noise: Image = Image::from_pixels(
+                (0..256u32)
+                    .map(|i| Pixel::new((i * 37) as u8, (i * 11) as u8, i as u8, 255))
+                    .collect(),
+                16,
+                16,
+                3,
+                0,
+            )
+            .unwrap();
+            let colors: Vec<(Pixel, f32)> = noise.dominant_colors(5);
+            assert_eq!(colors.len(), 5);
+            assert!((colors.iter().map(|c| c.1).sum::<f32>() - 1.0).abs() < 1e-4);
+            assert!(colors.windows(2).all(|w| w[0].1 >= w[1].1));
+
+            assert!(img.dominant_colors(0).is_empty());
+            assert!(Image::blank(0, 0, 3, 0)
+                .unwrap()
+                .dominant_colors(3)
+                .is_empty());
+        }
+
+        #[test]
+        fn encode_as_test() {
+            let pixels: Vec<Pixel> = (0..48u8)
+                .map(|i| Pixel::new(i * 5, 200 - i, i, 100 + i))
+                .collect();
+            let rgba: Image = Image::from_pixels(pixels.clone(), 8, 6, 4, 1).unwrap();
+
+            //dropping alpha writes a 3-channel header and no RGBA chunks
+            let encoded: Vec<u8> = encode_as(&rgba, 3).unwrap();
+            let header: QoiHeader = decode_header(&encoded).unwrap();
+            assert_eq!((header.channels, header.colorspace), (3, 1));
+            assert_eq!(analyze(&encoded).unwrap().rgba.chunks, 0);
+            assert!(analyze(&encode_from_image(&rgba)).unwrap().rgba.chunks > 0);
+            let decoded: Image = decode(encoded.clone()).unwrap();
+            assert_eq!((decoded.width, decoded.height, decoded.channels), (8, 6, 3));
+            assert!(decoded
+                .pixels
+                .iter()
+                .zip(&pixels)
+                .all(|(d, p)| *d == Pixel { a: 255, ..*p }));
+            let rgb: Image = Image::from_pixels(pixels.clone(), 8, 6, 3, 1).unwrap();
+            assert_eq!(encoded, encode_from_image(&rgb));
+
+            //adding alpha makes every pixel opaque
+            let encoded: Vec<u8> = encode_as(&rgb, 4).unwrap();
+            let decoded: Image = decode(encoded).unwrap();
+            assert_eq!(decoded.channels, 4);
+            assert!(decoded
+                .pixels
+                .iter()
+                .zip(&pixels)
+                .all(|(d, p)| *d == Pixel { a: 255, ..*p }));
+
+            assert_eq!(encode_as(&rgba, 4).unwrap(), encode_from_image(&rgba));
+            for channels in [0, 1, 2, 5] {
+                assert_eq!(
+                    encode_as(&rgba, channels).err(),
+                    Some(ImgError::InvalidFormat {
+                        channels,
+                        colorspace: 1
+                    })
+                );
+            }
+        }
+
+        #[test]
+        fn nearest_test() {
+            let palette: [Pixel; 4] = [
+                Pixel::new(0, 0, 0, 255),
+                Pixel::new(255, 255, 255, 255),
+                Pixel::new(200, 0, 0, 0),
+                Pixel::new(190, 0, 0, 255),
+            ];
+            assert_eq!(
+                Pixel::new(10, 20, 30, 255).nearest(&palette),
+                Some(&palette[0])
+            );
+            assert_eq!(
+                Pixel::new(180, 180, 180, 255).nearest(&palette),
+                Some(&palette[1])
+            );
+            assert_eq!(
+                Pixel::new(200, 0, 0, 255).nearest(&palette),
+                Some(&palette[3])
+            );
+            //without alpha the transparent red is closer
+            assert_eq!(
+                Pixel::new(200, 0, 0, 255).nearest_rgb(&palette),
+                Some(&palette[2])
+            );
+            assert_eq!(
+                Pixel::new(200, 0, 0, 10).nearest(&palette),
+                Some(&palette[2])
+            );
+
+            //ties go to the first entry, and every exact match is found
+            let grey: [Pixel; 2] = [
+                Pixel::new(100, 100, 100, 255),
+                Pixel::new(110, 110, 110, 255),
+            ];
+            assert_eq!(
+                Pixel::new(105, 105, 105, 255).nearest(&grey),
+                Some(&grey[0])
+            );
+            for p in &palette {
+                assert_eq!(p.nearest(&palette), Some(p));
+            }
+
+            assert_eq!(Pixel::new(1, 2, 3, 4).nearest(&[]), None);
+            assert_eq!(Pixel::new(1, 2, 3, 4).nearest_rgb(&[]), None);
+        }
+
+        #[test]
+        fn dither_to_palette_test() {
+            let black: Pixel = Pixel::new(0, 0, 0, 255);
+            let white: Pixel = Pixel::new(255, 255, 255, 255);
+            let palette: [Pixel; 2] = [black, white];
+
+            //a horizontal grey ramp keeps its average brightness, where nearest colour mapping turns it into two halves
+            let pixels: Vec<Pixel> = (0..64 * 16)
+                .map(|i| {
+                    let v: u8 = ((i % 64) * 4) as u8;
+                    Pixel::new(v, v, v, 255)
+                })
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 64, 16, 3, 0).unwrap();
+            let dithered: Image = img.dither_to_palette(&palette);
+            assert_eq!(
+                (dithered.width, dithered.height, dithered.channels),
+                (64, 16, 3)
+            );
+            assert!(dithered.pixels.iter().all(|p| palette.contains(p)));
+            let whites: usize = dithered.pixels.iter().filter(|&&p| p == white).count();
+            assert!((480..=528).contains(&whites), "{whites} white pixels");
+            //darker columns get fewer white pixels than lighter ones
+            let column_whites = |x: usize| {
+                (0..16)
+                    .filter(|y| dithered.pixels[y * 64 + x] == white)
+                    .count()
+            };
+            assert!(
+                (0..16).map(column_whites).sum::<usize>()
+                    < (48..64).map(column_whites).sum::<usize>()
+            );
+            //the lighter half is not plain white, so the error really was diffused
+            assert!((0..16 * 64).any(|i| i % 64 >= 32 && dithered.pixels[i] == black));
+
+            //palette colours are kept as they are, also along the edges of a single column
+            let pixels: Vec<Pixel> = (0..9)
+                .map(|i| if i % 2 == 0 { black } else { white })
+                .collect();
+            let column: Image = Image::from_pixels(pixels.clone(), 1, 9, 4, 0).unwrap();
+            assert!(column.dither_to_palette(&palette).pixels == pixels);
+            assert!(column.dither_to_palette(&[]).pixels == pixels);
+            assert!(Image::blank(0, 0, 4, 0)
+                .unwrap()
+                .dither_to_palette(&palette)
+                .pixels
+                .is_empty());
+
+            //a quantized palette works as well, and its dithered image still encodes and decodes
+            let (palette, _) = img.quantize(4);
+            let dithered: Image = img.dither_to_palette(&palette);
+            assert!(dithered.pixels.iter().all(|p| palette.contains(p)));
+            assert!(decode(encode_from_image(&dithered)).unwrap().pixels == dithered.pixels);
+        }
+
+        #[test]
+        fn premultiply_test() {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(200, 100, 255, 128),
+                Pixel::new(200, 100, 255, 255),
+                Pixel::new(200, 100, 255, 0),
+                Pixel::new(255, 255, 255, 1),
+            ];
+            let img: Image = Image::from_pixels(pixels, 4, 1, 4, 0).unwrap();
+            let pre: Image = img.premultiply();
+            assert_eq!(pre.pixels[0], Pixel::new(100, 50, 128, 128));
+            assert_eq!(pre.pixels[1], img.pixels[1]);
+            assert_eq!(pre.pixels[2], Pixel::new(0, 0, 0, 0));
+            assert_eq!(pre.pixels[3], Pixel::new(1, 1, 1, 1));
+            let back: Image = pre.unpremultiply();
+            assert_eq!(back.pixels[1], img.pixels[1]);
+            assert_eq!(back.pixels[2], Pixel::new(0, 0, 0, 0));
+            assert_eq!(back.pixels[3], Pixel::new(255, 255, 255, 1));
+
+            //a premultiplied channel larger than alpha is clamped
+            let bad: Image =
+                Image::from_pixels(vec![Pixel::new(200, 0, 0, 100)], 1, 1, 4, 0).unwrap();
+            assert_eq!(bad.unpremultiply().pixels[0], Pixel::new(255, 0, 0, 100));
+
+            for a in [16u8, 64, 128, 200, 255] {
+                let pixels: Vec<Pixel> = (0..=255)
+                    .map(|c| Pixel::new(c, 255 - c, c / 2, a))
+                    .collect();
+                let img: Image = Image::from_pixels(pixels, 256, 1, 4, 0).unwrap();
+                let back: Image = img.premultiply().unpremultiply();
+                let tolerance: i16 = (255 / a as i16 + 1) / 2;
+                for (p, q) in img.pixels.iter().zip(&back.pixels) {
+                    for (c, d) in p.to_array().into_iter().zip(q.to_array()) {
+                        assert!((c as i16 - d as i16).abs() <= tolerance, "{p:?} {q:?}");
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn decode_slice_test() {
+            let pixels: Vec<Pixel> = (0..20).map(|i| Pixel::new(i * 5, 100, i, 200)).collect();
+            let img: Image = Image::from_pixels(pixels, 5, 4, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
+            assert!(decode_slice(&bytes).unwrap() == img);
+            //the stream may sit inside a larger buffer
+            let mut padded: Vec<u8> = vec![0xEE; 3];
+            padded.extend_from_slice(&bytes);
+            assert!(decode_slice(&padded[3..]).unwrap() == img);
+            assert_eq!(
+                decode_slice(&bytes[..10]).err(),
+                Some(ImgError::HeaderError)
+            );
+            assert_eq!(
+                decode_slice(&bytes[..bytes.len() - 3]).err(),
+                Some(ImgError::DecodeError)
+            );
+        }
+
+        #[test]
+        fn short_input_test() {
+            assert_eq!(
+                decode(vec![0x71, 0x6f, 0x69]).err(),
+                Some(ImgError::HeaderError)
+            );
+            assert_eq!(decode(Vec::new()).err(), Some(ImgError::HeaderError));
+            //a complete header without the end marker is still too short
+            let bytes: Vec<u8> = encode_from_image(&Image::blank(0, 0, 4, 0).unwrap());
+            assert_eq!(bytes.len(), 22);
+            assert!(decode_slice(&bytes).is_ok());
+            for len in 0..22 {
+                assert_eq!(
+                    decode_slice(&bytes[..len]).err(),
+                    Some(ImgError::HeaderError)
+                );
+                assert_eq!(
+                    decode_with_stats(&bytes[..len]).err(),
+                    Some(ImgError::HeaderError)
+                );
+            }
+        }
+
+        #[test]
+        fn qoi_bytes_test() {
+            let img: Image = Image::solid(3, 2, Pixel::new(1, 2, 3, 4), 4, 0).unwrap();
+            let bytes: Vec<u8> = img.to_qoi_bytes();
+            assert_eq!(bytes, encode_from_image(&img));
+            assert!(Image::from_qoi_bytes(&bytes).unwrap() == img);
+            assert_eq!(
+                Image::from_qoi_bytes(&bytes[1..]).err(),
+                Some(ImgError::HeaderError)
+            );
+        }
+
+        #[test]
+        fn vec_conversion_test() {
+            let img: Image = Image::solid(3, 2, Pixel::new(1, 2, 3, 4), 4, 0).unwrap();
+            let encoded: Vec<u8> = Vec::<u8>::from(img.clone());
+            assert_eq!(encoded, encode_from_image(&img));
+            assert!(Image::try_from(encoded.clone()).unwrap() == img);
+            let decoded: Image = encoded.try_into().unwrap();
+            assert!(decoded == img);
+
+            assert_eq!(
+                Image::try_from(b"garbage".to_vec()).err(),
+                Some(ImgError::HeaderError)
+            );
+        }
+
+        #[test]
+        fn encode_progress_test() {
+            let pixels: Vec<Pixel> = (0..300_000u32)
+                .map(|i| Pixel::new(i as u8, (i >> 8) as u8, 0, 255))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 1000, 300, 4, 0).unwrap();
+            let mut calls: Vec<(u64, u64)> = Vec::new();
+            let bytes: Vec<u8> =
+                encode_with_progress(&img, |done, total| calls.push((done, total)));
+            assert_eq!(bytes, encode_from_image(&img));
+            //no runs, so every pixel is its own chunk and reports land exactly on the interval
+            let done: Vec<u64> = calls.iter().map(|(done, total)| *done).collect();
+            assert_eq!(done, vec![65536, 131072, 196608, 262144, 300_000]);
+            assert!(calls.iter().all(|(done, total)| *total == 300_000));
 
-        let pixel: Pixel = Pixel::new(r, g, b, prev_pixel.a);
-        pixel
-    }
+            let mut calls: Vec<(u64, u64)> = Vec::new();
+            encode_with_progress(&Image::blank(0, 0, 4, 0).unwrap(), |done, total| {
+                calls.push((done, total))
+            });
+            assert_eq!(calls, vec![(0, 0)]);
+        }
 
-    fn dec_run() {}
+        #[test]
+        fn cancellable_test() {
+            let pixels: Vec<Pixel> = (0..64 * 64)
+                .map(|i| Pixel::new(i as u8, (i / 64) as u8, 7, 255))
+                .collect();
+            let img: Image = Image::from_pixels(pixels, 64, 64, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
 
-    pub fn decode(mut bytes: Vec<u8>) -> Result<Image, ImgError> {
-        let width: u32;
-        let height: u32;
-        let channels: u8;
-        let colorspace: u8;
-
-        let mut prev_pixel: Pixel = Pixel {
-            r: 0u8,
-            g: 0u8,
-            b: 0u8,
-            a: 255u8,
-        };
+            let flag: AtomicBool = AtomicBool::new(false);
+            assert_eq!(encode_cancellable(&img, &flag), Some(bytes.clone()));
+            assert!(decode_cancellable(&bytes, &flag).unwrap().unwrap() == img);
+            assert_eq!(
+                decode_cancellable(&bytes[1..], &flag).err(),
+                Some(ImgError::HeaderError)
+            );
 
-        let mut prev_buffer: [Pixel; 64] = array_init::array_init(|_| Pixel::new(0, 0, 0, 0));
+            flag.store(true, Ordering::Relaxed);
+            assert_eq!(encode_cancellable(&img, &flag), None);
+            assert!(decode_cancellable(&bytes, &flag).unwrap().is_none());
+            //a malformed header is still reported
+            assert_eq!(
+                decode_cancellable(&bytes[1..], &flag).err(),
+                Some(ImgError::HeaderError)
+            );
+        }
 
-        match read_header(&bytes[0..14]) {
-            Ok((w, h, ch, c)) => {
-                width = w;
-                height = h;
-                channels = ch;
-                colorspace = c;
-            }
-            Err(err) => {
-                return Err(err);
-            }
+        #[test]
+        fn index_initial_run_test() {
+            //the leading run repeats the implicit previous pixel, which the decoder puts into slot 53
+            let black: Pixel = Pixel::new(0, 0, 0, 255);
+            let img: Image = Image::from_pixels(
+                vec![black, black, black, Pixel::new(100, 10, 90, 255), black],
+                5,
+                1,
+                4,
+                0,
+            )
+            .unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
+            assert_eq!(
+                bytes[14..bytes.len() - 8],
+                [QOI_OP_RUN | 2, QOI_OP_RGB, 100, 10, 90, QOI_OP_INDEX | 53]
+            );
+            assert!(decode(bytes).unwrap() == img);
         }
-        let mut pixels: Vec<Pixel> = Vec::with_capacity((width * height * 4) as usize);
 
-        if bytes[bytes.len() - 1] == 1 {
-            for i in 2..9 {
-                if bytes[bytes.len() - i] != 0 {
-                    debug!("Ending bytes not present.");
-                    return Err(ImgError::DecodeError);
-                }
+        #[test]
+        fn rgb_encode_test() {
+            //alpha values of a 3-channel image are meaningless and must not produce RGBA chunks
+            let mut pixels: Vec<Pixel> = Vec::new();
+            for i in 0..64u8 {
+                pixels.push(Pixel::new(i * 4, 255 - i * 2, i, i.wrapping_mul(37)));
             }
-            for i in 0..8 {
-                bytes.pop();
+            let img: Image = Image::from_pixels(pixels, 8, 8, 3, 0).unwrap();
+            let (decoded, stats) = decode_with_stats(&encode_from_image(&img)).unwrap();
+
+            assert_eq!(stats.rgba, 0);
+            for (dec, pix) in decoded.pixels.iter().zip(img.pixels.iter()) {
+                assert_eq!(*dec, Pixel { a: 255, ..*pix });
             }
-        } else {
-            debug!("Ending bytes not present.");
-            return Err(ImgError::DecodeError);
         }
 
-        let mut i: usize = 14;
+        #[test]
+        fn compression_advice_test() {
+            let flat: Image =
+                Image::from_pixels(vec![Pixel::new(20, 40, 60, 255); 64 * 64], 64, 64, 4, 0)
+                    .unwrap();
+            assert_eq!(flat.compression_advice(), CompressionAdvice::GoodFit);
 
-        while i < bytes.len() {
-            match read_tag(bytes[i]) {
-                Ok(tag) => match tag {
-                    ChunkType::RGB => {
-                        let dec_pix: Pixel = dec_rgb(&bytes[i..i + 4], prev_pixel.a);
-                        prev_pixel = dec_pix.clone();
-                        prev_buffer[color_hash(&dec_pix) as usize] = dec_pix.clone();
-                        pixels.push(dec_pix);
-                        i += 3;
-                    }
-                    ChunkType::RGBA => {
-                        let dec_pix: Pixel = dec_rgba(&bytes[i..i + 5]);
-                        prev_pixel = dec_pix.clone();
-                        prev_buffer[color_hash(&dec_pix) as usize] = dec_pix.clone();
-                        pixels.push(dec_pix);
-                        i += 4;
-                    }
-                    ChunkType::Diff => {
-                        let dec_pix: Pixel = dec_diff(bytes[i], &prev_pixel);
-                        prev_pixel = dec_pix.clone();
-                        prev_buffer[color_hash(&dec_pix) as usize] = dec_pix.clone();
-                        pixels.push(dec_pix);
-                    }
-                    ChunkType::Index => {
-                        let dec_pix: Pixel = prev_buffer[bytes[i] as usize];
-                        prev_pixel = dec_pix.clone();
-                        prev_buffer[color_hash(&dec_pix) as usize] = dec_pix.clone();
-                        pixels.push(dec_pix);
-                    }
-                    ChunkType::Luma => {
-                        let dec_pix: Pixel = dec_luma(&bytes[i..i + 2], &prev_pixel);
-                        prev_pixel = dec_pix.clone();
-                        prev_buffer[color_hash(&dec_pix) as usize] = dec_pix.clone();
-                        pixels.push(dec_pix);
-                        i += 1;
-                    }
-                    ChunkType::Run => {
-                        let length: u8 = (bytes[i] & 0b00111111) + RUN_BIAS;
-                        for j in 0..length {
-                            pixels.push(prev_pixel.clone());
-                        }
-                        prev_buffer[color_hash(&prev_pixel) as usize] = prev_pixel.clone();
-                    }
-                },
-                Err(err) => return Err(err),
-            }
-            i += 1;
+            let mut state: u32 = 0xC0FF_EE01;
+            let noise: Vec<Pixel> = (0..64 * 64)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    Pixel::from_u32_rgba(state)
+                })
+                .collect();
+            let noise: Image = Image::from_pixels(noise, 64, 64, 4, 0).unwrap();
+            assert_eq!(noise.compression_advice(), CompressionAdvice::NotSuitable);
+
+            //unrelated colours repeated three times each: smaller than raw, but mostly literal chunks
+            let stripes: Vec<Pixel> = (0..64 * 64)
+                .map(|i: u32| i / 3)
+                .map(|i| Pixel::new((i * 97) as u8, (i * 61) as u8, (i * 13) as u8, 255))
+                .collect();
+            let stripes: Image = Image::from_pixels(stripes, 64, 64, 4, 0).unwrap();
+            assert_eq!(
+                stripes.compression_advice(),
+                CompressionAdvice::ConsiderPosterize
+            );
         }
 
-        if pixels.len() as u32 != height * width {
-            debug!("h*w: {}", height * width);
-            debug!("n pixels: {}", pixels.len());
-            return Err(ImgError::DecodeError);
+        #[test]
+        fn to_bytes_channels_test() {
+            let data: Vec<u8> = (0..5 * 3 * 3).map(|i| i as u8).collect();
+            let rgb: Image = Image::new(data.clone(), 5, 3, 3, 0).unwrap();
+            assert_eq!(rgb.to_bytes().len(), 5 * 3 * 3);
+            assert_eq!(rgb.to_bytes(), data);
+            assert_eq!(rgb.to_bytes_rgba().len(), 5 * 3 * 4);
+
+            let decoded: Image = decode(encode_from_image(&rgb)).unwrap();
+            assert_eq!(decoded.to_bytes(), data);
+
+            let rgba: Image = Image::new(vec![9; 4 * 4], 2, 2, 4, 0).unwrap();
+            assert_eq!(rgba.to_bytes(), vec![9; 4 * 4]);
         }
 
-        let img = Image::from_pixels(pixels, height, width, channels, colorspace);
-        Ok(img)
-    }
+        #[test]
+        fn trailing_whitespace_test() {
+            let img: Image =
+                Image::from_pixels(vec![Pixel::new(5, 6, 7, 255); 6], 3, 2, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
 
-    #[cfg(test)]
-    mod tests {
+            for suffix in [&b"\n"[..], b"\n\n", b"\r\n"] {
+                let mut padded: Vec<u8> = bytes.clone();
+                padded.extend_from_slice(suffix);
+                let decoded: Image = decode(padded).unwrap();
+                assert_eq!(decoded.pixels, vec![Pixel::new(5, 6, 7, 255); 6]);
+            }
 
-        use png::ColorType;
+            let mut garbage: Vec<u8> = bytes.clone();
+            garbage.push(b'x');
+            assert_eq!(decode(garbage).err(), Some(ImgError::DecodeError));
 
-        use super::*;
-        use std::io;
-        use std::io::{BufReader, Read};
+            let mut too_long: Vec<u8> = bytes.clone();
+            too_long.extend_from_slice(&[b'\n'; MAX_TRAILING_WHITESPACE + 1]);
+            assert_eq!(decode(too_long).err(), Some(ImgError::DecodeError));
+        }
 
         #[test]
-        fn diff_test() {
-            let level: LevelFilter = LevelFilter::Debug;
-            init(level).expect("Logger initialisation failed!");
-            let pix1: Pixel = Pixel::new(0, 0, 0, 255);
-            let pix2: Pixel = Pixel::new(255, 255, 255, 255);
+        fn decode_header_test() {
+            let img: Image =
+                Image::from_pixels(vec![Pixel::new(1, 2, 3, 255); 12], 4, 3, 3, 1).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
 
-            let pix3: Pixel = Pixel::new(155, 155, 155, 255);
-            let pix4: Pixel = Pixel::new(160, 160, 160, 255);
-
-            assert_eq!(pix1.diff(&pix2), (1, 1, 1));
-            assert_eq!(pix2.diff(&pix1), (-1, -1, -1));
-            assert_eq!(pix4.diff(&pix3), (5, 5, 5));
-            assert_eq!(pix3.diff(&pix4), (-5, -5, -5));
+            let header: QoiHeader = decode_header(&bytes).unwrap();
+            assert_eq!(
+                header,
+                QoiHeader {
+                    width: 4,
+                    height: 3,
+                    channels: 3,
+                    colorspace: 1,
+                }
+            );
+            assert_eq!(decode_header(&bytes[..10]), Err(ImgError::HeaderError));
+            assert_eq!(decode_header(&bytes[1..]), Err(ImgError::HeaderError));
         }
 
-        /* #[test]
-        fn qoi_to_qoi_test() -> io::Result<()> {
-            //Open path to test images
-            let path: &Path = Path::new("./qoi_test_images/");
-            let dir: ReadDir = match path.read_dir() {
-                Ok(d) => d,
-                Err(e) => panic!("Error reading path {e:?}"),
+        #[test]
+        fn decode_with_stats_test() {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(10, 20, 30, 40),   //RGBA
+                Pixel::new(200, 100, 50, 40), //RGB
+                Pixel::new(201, 101, 51, 40), //Diff
+                Pixel::new(220, 120, 70, 40), //Luma
+                Pixel::new(220, 120, 70, 40), //Run
+                Pixel::new(220, 120, 70, 40),
+                Pixel::new(10, 20, 30, 40), //Index
+                Pixel::new(10, 20, 30, 40), //Run
+            ];
+            let img: Image = Image::from_pixels(pixels, 4, 2, 4, 0).unwrap();
+            let mut bytes: Vec<u8> = encode_from_image(&img);
+            assert_eq!(bytes.len(), 14 + 5 + 4 + 1 + 2 + 1 + 1 + 1 + 8);
+            let (decoded, stats) = decode_with_stats(&bytes).unwrap();
+
+            assert_eq!(decoded.pixels, img.pixels);
+            let expected: DecodeStats = DecodeStats {
+                pixels: 8,
+                bytes: 37,
+                run: 2,
+                index: 1,
+                luma: 1,
+                diff: 1,
+                rgb: 1,
+                rgba: 1,
             };
-            //Loop over files in directory, attempt to decode .qoi images and reencode 
-            for entry in dir {
+            assert_eq!(stats, expected);
+            //trailing whitespace is not consumed
+            bytes.extend_from_slice(b"\n");
+            assert_eq!(decode_with_stats(&bytes).unwrap().1, expected);
 
-                let file_path = match entry {
-                    Ok(d) => d.path(),
-                    Err(e) => panic!("Non-functional dir entry! \n {e:?}")
-                };
-                let file_path_str = match file_path.to_str() {
-                    Some(s) => s,
-                    None => ""
-                };
-                if !(file_path_str.contains(".qoi")) {
-                    continue;
+            //a hand-written 3x1 stream: a run of the initial black pixel, an rgb pixel and a run repeating it
+            let tiny: Vec<u8> = [
+                &b"qoif"[..],
+                &[0, 0, 0, 3, 0, 0, 0, 1, 3, 0],
+                &[QOI_OP_RUN, QOI_OP_RGB, 1, 2, 3, QOI_OP_RUN],
+                &End::new().bytes,
+            ]
+            .concat();
+            let (decoded, stats) = decode_with_stats(&tiny).unwrap();
+            let rgb: Pixel = Pixel::new(1, 2, 3, 255);
+            assert_eq!(decoded.pixels, vec![Pixel::new(0, 0, 0, 255), rgb, rgb]);
+            assert_eq!(
+                stats,
+                DecodeStats {
+                    pixels: 3,
+                    bytes: 28,
+                    run: 2,
+                    rgb: 1,
+                    ..DecodeStats::default()
                 }
-                
-                let file = match File::open(&file_path) {
-                    Ok(f) => f,
-                    Err(e) => panic!("Error reading file with path {:?}", file_path_str),
-                };
-                let mut reader = BufReader::new(file);
-                let mut bytes: Vec<u8> = Vec::new();
+            );
+        }
 
-                reader.read_to_end(&mut bytes)?;
+        #[test]
+        fn decode_capacity_test() {
+            let img: Image =
+                Image::from_pixels(vec![Pixel::new(3, 3, 3, 255); 100], 10, 10, 4, 0).unwrap();
+            let decoded: Image = decode(encode_from_image(&img)).unwrap();
+            assert_eq!(decoded.pixels.capacity(), 100);
 
-                let output_image: super::Image;
-                match super::decode(bytes) {
-                    Ok(img) => output_image = img,
-                    Err(err) => panic!("Image decode failed for {:?}" , file_path.to_str())
-                }
-                let mut name = match file_path.file_name() {
-                    Some(s) => match s.to_str() {
-                        Some(ss) => ss,
-                        None => panic!("File Name Error!")
-                    },
-                    None => panic!("File Name Error!"),
-                };
-                name = match name.strip_suffix(".qoi") {
-                    Some(n) => n,
-                    None => name,
-                };
-                write_to_file(encode_from_image(output_image), name).expect("Writing image failed!");
-            }
-            
-            Ok(())
+            //header claiming u32::MAX x u32::MAX pixels followed by a single run
+            let mut bytes: Vec<u8> = encode_from_image(&img);
+            bytes[4..12].copy_from_slice(&[0xFF; 8]);
+            assert_eq!(
+                decode(bytes).err(),
+                Some(ImgError::PixelCountMismatch {
+                    expected: u32::MAX as usize * u32::MAX as usize,
+                    actual: 100
+                })
+            );
         }
 
         #[test]
-        fn png_to_qoi_test() -> io::Result<()> {
-            //Open path to test images
-            let path: &Path = Path::new("./qoi_test_images/");
-            let dir: ReadDir = match path.read_dir() {
-                Ok(d) => d,
-                Err(e) => panic!("Error reading path {e:?}"),
+        fn verify_test() {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(10, 20, 30, 40),
+                Pixel::new(200, 100, 50, 40),
+                Pixel::new(200, 100, 50, 40),
+                Pixel::new(220, 120, 70, 40),
+            ];
+            let img: Image = Image::from_pixels(pixels, 2, 2, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
+            assert_eq!(verify(&bytes), Ok(()));
+
+            let mut bad_magic: Vec<u8> = bytes.clone();
+            bad_magic[0] = b'x';
+            assert_eq!(verify(&bad_magic), Err(ImgError::HeaderError));
+
+            let mut bad_end: Vec<u8> = bytes.clone();
+            *bad_end.last_mut().unwrap() = 2;
+            assert_eq!(verify(&bad_end), Err(ImgError::DecodeError));
+
+            let mut bad_count: Vec<u8> = bytes.clone();
+            bad_count[11] = 3;
+            let mismatch: ImgError = ImgError::PixelCountMismatch {
+                expected: 6,
+                actual: 4,
             };
-            //Loop over files in directory, attempt to decode png and encode as qoi, compare to qoi
-            for entry in dir {
+            assert_eq!(verify(&bad_count), Err(mismatch.clone()));
+            assert_eq!(decode_slice(&bad_count).err(), Some(mismatch.clone()));
+            #[cfg(feature = "std")]
+            assert_eq!(
+                decode_from_reader(&mut io::Cursor::new(&bad_count)).err(),
+                Some(ImgError::DecodeError)
+            );
+            assert_eq!(decode_into(&bad_count, &mut [0; 24]), Err(mismatch.clone()));
+            assert_eq!(
+                mismatch.to_string(),
+                "header declares 6 pixels but the stream contains 4"
+            );
 
-                let file_path = match entry {
-                    Ok(d) => d.path(),
-                    Err(e) => panic!("Non-functional dir entry! \n {e:?}")
-                };
-                let file_path_str = match file_path.to_str() {
-                    Some(s) => s,
-                    None => ""
-                };
-                if !(file_path_str.contains(".png")) {
-                    continue;
-                }
-                debug!("{:}",file_path_str);
-                let file = match File::open(&file_path) {
-                    Ok(f) => f,
-                    Err(e) => panic!("Cannot read file! \n {e:?}")
-                };
-                let decoder = png::Decoder::new(file);
-                let mut reader = match decoder.read_info() {
-                    Ok(reader) => reader,
-                    Err(e) => panic!("ERROR: couldn't decode file: {e:}"),
-                };
-                //read image metadata
-                let width: u32 = reader.info().width;
-                let height: u32 = reader.info().height;
-                //for now: hardcoded to 4
-                let channels = match reader.info().color_type {
-                    ColorType::Rgb => 3,
-                    ColorType::Rgba => 4,
-                    _ => panic!("ERROR: Incompatible png file!")
-                };
+            //drop the last byte of the final chunk
+            let mut truncated: Vec<u8> = bytes[..bytes.len() - 9].to_vec();
+            truncated.extend_from_slice(&End::new().bytes);
+            assert_eq!(verify(&truncated), Err(ImgError::DecodeError));
+        }
 
-                //create buffer matching the size of png-decoder output, writing size to output
-                let mut buf = vec![0; reader.output_buffer_size()];
-                let info = match reader.next_frame(&mut buf) {
-                    Ok(i) => i,
-                    Err(e) => panic!("ERROR: {e:?}"),
-                };
-                let bytes = &buf[..info.buffer_size()];
-                let byte_vec: Vec<u8> = bytes.to_vec();
+        #[test]
+        fn truncated_chunk_test() {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(10, 20, 30, 40),
+                Pixel::new(200, 100, 50, 40),
+                Pixel::new(202, 101, 50, 40),
+                Pixel::new(220, 120, 70, 40),
+                Pixel::new(10, 20, 30, 40),
+                Pixel::new(10, 20, 30, 40),
+            ];
+            let img: Image = Image::from_pixels(pixels, 3, 2, 4, 0).unwrap();
+            let bytes: Vec<u8> = encode_from_image(&img);
+            let body_end: usize = bytes.len() - 8;
 
-                //create bitmap data from raw byte vector
-                let img: Image = match Image::new(byte_vec, height, width, channels, 0) {
-                    Ok(image) => image,
-                    Err(err) => panic!("Problem generating image: {:?}", err),
-                };
+            //cut the chunk data short at every offset, but keep a valid end marker
+            for offset in 14..body_end {
+                let mut truncated: Vec<u8> = bytes[..offset].to_vec();
+                truncated.extend_from_slice(&bytes[body_end..]);
+                assert!(decode(truncated).is_err(), "offset {offset} decoded");
+            }
+            assert!(decode(bytes).is_ok());
+        }
 
-                let encoded_buffer = super::encode_from_image(img);
-                let mut name =  match file_path.file_name() {
-                    None => panic!("whoops!"),
-                    Some(n) => match n.to_str() {
-                        None => panic!("im shiddin"),
-                        Some(s) => s, 
-                    },
-                };
-                name = match name.strip_suffix(".png") {
-                    Some(n) => n,
-                    None => name,
-                };
-                write_to_file(encoded_buffer,name ).expect("Can't write resulting file!");
+        #[test]
+        fn exact_encoded_len_test() {
+            let mut noise: Vec<Pixel> = Vec::new();
+            let mut state: u32 = 0x1234_5678;
+            for i in 0..1000 {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                noise.push(Pixel::from_u32_rgba(state));
+            }
+            let images: [Image; 4] = [
+                Image::from_pixels(vec![Pixel::new(1, 2, 3, 4)], 1, 1, 4, 0).unwrap(),
+                Image::from_pixels(vec![Pixel::new(0, 0, 0, 255)], 1, 1, 3, 0).unwrap(),
+                Image::from_pixels(vec![Pixel::new(40, 50, 60, 255); 500], 25, 20, 3, 0).unwrap(),
+                Image::from_pixels(noise, 25, 40, 4, 0).unwrap(),
+            ];
+            for img in &images {
+                assert_eq!(exact_encoded_len(img), encode_from_image(img).len());
             }
-            
-            Ok(())
         }
- */
+
+        #[test]
+        fn stream_diff_test() {
+            let mut expected: Vec<u8> = encode_from_image(
+                &Image::from_pixels(
+                    vec![
+                        Pixel::new(10, 10, 10, 255),
+                        Pixel::new(11, 11, 11, 255),
+                        Pixel::new(200, 20, 70, 255),
+                    ],
+                    3,
+                    1,
+                    4,
+                    0,
+                )
+                .unwrap(),
+            );
+            assert_eq!(stream_diff(&expected, &expected.clone()), None);
+
+            let actual: Vec<u8> = expected.clone();
+            //replace the diff chunk of the second pixel with a luma chunk
+            expected[16] = QOI_OP_LUMA | 33;
+            let report: String = stream_diff(&expected, &actual).unwrap();
+            assert!(report.contains("differ at byte 16"));
+            assert!(report.contains("Ok(Luma) at byte 16"));
+            assert!(report.contains("Ok(Diff) at byte 16"));
+
+            let report: String = stream_diff(&actual, &actual[..20]).unwrap();
+            assert!(report.contains("differ at byte 20"));
+        }
+
         #[test]
         fn tag_test() {
             //init().expect("Logger initialisation failed!");
@@ -1023,6 +5648,17 @@ pub mod qoi_lib {
             assert_eq!(Ok(ChunkType::Run), super::read_tag(test_run));
         }
 
+        #[test]
+        fn index_mask_test() {
+            let mut state: DecoderState = DecoderState::new();
+            state.prev_buffer[63] = Pixel::new(1, 2, 3, 4);
+
+            for byte in [0b00111111, 0b11111111] {
+                let (pixel, count) = state.decode_chunk(&ChunkType::Index, &[byte]);
+                assert_eq!((pixel, count), (Pixel::new(1, 2, 3, 4), 1));
+            }
+        }
+
         #[test]
         fn sub_decoders_test() {
             //init().expect("Logger initialisation failed!");
@@ -1058,5 +5694,264 @@ pub mod qoi_lib {
 
             assert_eq!(pix, dec_luma(&byte[0..2], &prev));
         }
+
+        #[test]
+        fn map_channel_test() {
+            let pixels: Vec<Pixel> = vec![
+                Pixel::new(10, 20, 30, 40),
+                Pixel::new(100, 110, 120, 130),
+                Pixel::new(200, 210, 220, 230),
+                Pixel::new(0, 255, 0, 255),
+            ];
+            let mut img: Image = Image::from_pixels(pixels.clone(), 2, 2, 4, 0).unwrap();
+            img.map_channel(Channel::Red, |r| r.saturating_mul(2));
+
+            let expected_red: [u8; 4] = [20, 200, 255, 0];
+            for (i, pixel) in img.pixels.iter().enumerate() {
+                assert_eq!(pixel.r, expected_red[i]);
+                assert_eq!(pixel.g, pixels[i].g);
+                assert_eq!(pixel.b, pixels[i].b);
+                assert_eq!(pixel.a, pixels[i].a);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod property_tests {
+
+        use super::*;
+        use proptest::prelude::*;
+
+        //xorshift32, good enough for reproducible test images
+        struct Rng(u32);
+
+        impl Rng {
+            fn next(&mut self) -> u32 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 17;
+                self.0 ^= self.0 << 5;
+                self.0
+            }
+
+            fn below(&mut self, n: u32) -> u32 {
+                self.next() % n
+            }
+
+            fn byte(&mut self) -> u8 {
+                self.next() as u8
+            }
+        }
+
+        #[derive(Clone, Copy, Debug)]
+        enum Kind {
+            Noise,
+            Opaque,
+            HeavyAlpha,
+            SingleColor,
+            //small steps between neighbours, so diff and luma chunks show up
+            Gradient,
+            //a handful of colours including the initial previous pixel, so index chunks dominate
+            Palette,
+        }
+
+        fn random_image(rng: &mut Rng, kind: Kind) -> Image {
+            let width: u32 = rng.below(40) + 1;
+            let height: u32 = rng.below(40) + 1;
+            let n: usize = (width * height) as usize;
+            let mut pixels: Vec<Pixel> = Vec::with_capacity(n);
+            let base: Pixel = Pixel::new(rng.byte(), rng.byte(), rng.byte(), rng.byte());
+            let mut walk: Pixel = base;
+            for _ in 0..n {
+                let pixel: Pixel = match kind {
+                    Kind::Noise => Pixel::new(rng.byte(), rng.byte(), rng.byte(), rng.byte()),
+                    Kind::Opaque => Pixel::new(rng.byte(), rng.byte(), rng.byte(), 255),
+                    Kind::HeavyAlpha => {
+                        Pixel::new(rng.byte(), rng.byte(), rng.byte(), rng.below(4) as u8 * 85)
+                    }
+                    Kind::SingleColor => base,
+                    Kind::Palette => [
+                        Pixel::new(0, 0, 0, 255),
+                        Pixel::new(0, 0, 0, 0),
+                        base,
+                        Pixel::new(200, 10, 90, 255),
+                    ][rng.below(4) as usize],
+                    Kind::Gradient => {
+                        let step = |v: u8, rng: &mut Rng| {
+                            v.wrapping_add(rng.below(40) as u8).wrapping_sub(20)
+                        };
+                        walk = Pixel::new(
+                            step(walk.r, rng),
+                            step(walk.g, rng),
+                            step(walk.b, rng),
+                            if rng.below(16) == 0 {
+                                rng.byte()
+                            } else {
+                                walk.a
+                            },
+                        );
+                        walk
+                    }
+                };
+                //occasionally repeat the previous pixel to produce runs
+                match pixels.last() {
+                    Some(prev) if rng.below(4) == 0 => pixels.push(*prev),
+                    _ => pixels.push(pixel),
+                }
+            }
+            Image::from_pixels(pixels, width, height, 4, 0).unwrap()
+        }
+
+        //Feeds `bytes` to every decoding entry point; they must return an error rather than panic
+        fn decode_all(bytes: &[u8]) {
+            let _ = decode_header(bytes);
+            let _ = verify(bytes);
+            let _ = analyze(bytes);
+            let _ = decode_slice(bytes);
+            let _ = decode_to_rgba(bytes);
+            let _ = decode(bytes.to_vec());
+            let _ = decode_with_stats(bytes);
+            #[cfg(feature = "std")]
+            let _ = decode_from_reader(&mut std::io::Cursor::new(bytes));
+            let _ = decode_into(bytes, &mut [0; 256]);
+            let _ = decode_bmp(bytes);
+            let _ = decode_sequence(bytes);
+            #[cfg(feature = "rayon")]
+            let _ = decode_parallel(bytes);
+        }
+
+        #[test]
+        fn malformed_input_test() {
+            let mut rng: Rng = Rng(0xF022_0001);
+            //short inputs, both random and starting with the magic bytes
+            for len in 0..64 {
+                for _ in 0..50 {
+                    let mut bytes: Vec<u8> = (0..len).map(|_| rng.byte()).collect();
+                    decode_all(&bytes);
+                    let magic: &[u8] = match rng.below(4) {
+                        0 => b"qoif",
+                        1 => b"qoim",
+                        2 => b"qoia",
+                        _ => b"BM",
+                    };
+                    let n: usize = magic.len().min(len);
+                    bytes[..n].copy_from_slice(&magic[..n]);
+                    decode_all(&bytes);
+                }
+            }
+
+            //truncations and single-byte corruptions of valid streams
+            for case in 0..20u32 {
+                let img: Image = random_image(&mut rng, Kind::Palette);
+                let bytes: Vec<u8> = encode_from_image(&img);
+                for len in 0..bytes.len() {
+                    decode_all(&bytes[..len]);
+                }
+                for _ in 0..200 {
+                    let mut corrupt: Vec<u8> = bytes.clone();
+                    let i: usize = rng.below(corrupt.len() as u32) as usize;
+                    corrupt[i] = rng.byte();
+                    decode_all(&corrupt);
+                }
+            }
+
+            //dimensions whose pixel count is zero or overflows
+            for (width, height) in [(0, u32::MAX), (u32::MAX, u32::MAX), (1 << 16, 1 << 16)] {
+                let mut bytes: Vec<u8> = b"qoif".to_vec();
+                bytes.extend_from_slice(&width.to_be_bytes());
+                bytes.extend_from_slice(&height.to_be_bytes());
+                bytes.extend_from_slice(&[4, 0, QOI_OP_RUN | 61, 0, 0, 0, 0, 0, 0, 0, 1]);
+                decode_all(&bytes);
+            }
+
+            //two empty bands whose heights add up to more than u32::MAX
+            let band: Vec<u8> = encode_from_image(&Image::blank(0, u32::MAX, 4, 0).unwrap());
+            let mut container: Vec<u8> = b"qoim".to_vec();
+            container.extend_from_slice(&2u32.to_be_bytes());
+            for _ in 0..2 {
+                container.extend_from_slice(&(band.len() as u32).to_be_bytes());
+                container.extend_from_slice(&band);
+            }
+            decode_all(&container);
+        }
+
+        //proptest strategy for pixels, mixing a small palette (runs and index hits) with arbitrary colours
+        fn arb_pixel() -> impl Strategy<Value = Pixel> {
+            prop_oneof![
+                prop::sample::select(vec![
+                    Pixel::new(0, 0, 0, 255),
+                    Pixel::new(0, 0, 0, 0),
+                    Pixel::new(255, 255, 255, 255),
+                    Pixel::new(100, 10, 90, 128),
+                ]),
+                any::<u32>().prop_map(Pixel::from_u32),
+            ]
+        }
+
+        //width, height, channels, colorspace and pixels of an image; Image itself is not Debug
+        fn arb_image() -> impl Strategy<Value = (u32, u32, u8, u8, Vec<Pixel>)> {
+            (0..=24u32, 0..=24u32, 3..=4u8, 0..=1u8).prop_flat_map(
+                |(w, h, channels, colorspace)| {
+                    (
+                        Just(w),
+                        Just(h),
+                        Just(channels),
+                        Just(colorspace),
+                        prop::collection::vec(arb_pixel(), (w * h) as usize),
+                    )
+                },
+            )
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(256))]
+
+            #[test]
+            fn proptest_roundtrip((w, h, channels, colorspace, pixels) in arb_image()) {
+                let img: Image = Image::from_pixels(pixels, w, h, channels, colorspace).unwrap();
+                let decoded: Image = decode(encode_from_image(&img)).unwrap();
+                prop_assert_eq!(
+                    (decoded.width, decoded.height, decoded.channels, decoded.colorspace),
+                    (w, h, channels, colorspace)
+                );
+                //3-channel images do not store alpha
+                let expected: Vec<Pixel> = match channels {
+                    3 => img.pixels.iter().map(|pixel| Pixel { a: 255, ..*pixel }).collect(),
+                    _ => img.pixels.clone(),
+                };
+                prop_assert_eq!(decoded.pixels, expected);
+            }
+        }
+
+        #[test]
+        fn random_roundtrip_test() {
+            const SEED: u32 = 0x5EED_1234;
+            let kinds: [Kind; 6] = [
+                Kind::Noise,
+                Kind::Opaque,
+                Kind::HeavyAlpha,
+                Kind::SingleColor,
+                Kind::Gradient,
+                Kind::Palette,
+            ];
+            for case in 0..500u32 {
+                let seed: u32 = SEED.wrapping_add(case.wrapping_mul(0x9E37_79B9)) | 1;
+                let mut rng: Rng = Rng(seed);
+                let kind: Kind = kinds[case as usize % kinds.len()];
+                let img: Image = random_image(&mut rng, kind);
+                let decoded: Image = match decode(encode_from_image(&img)) {
+                    Ok(decoded) => decoded,
+                    Err(err) => panic!("decode failed for seed {seed:#x} ({kind:?}): {err:?}"),
+                };
+                assert_eq!(
+                    (decoded.width, decoded.height),
+                    (img.width, img.height),
+                    "seed {seed:#x} ({kind:?})"
+                );
+                assert!(
+                    decoded.pixels == img.pixels,
+                    "pixel mismatch for seed {seed:#x} ({kind:?})"
+                );
+            }
+        }
     }
 }
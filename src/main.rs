@@ -1,16 +1,25 @@
 
 use clap::{Args,Parser, Subcommand};
+use std::error::Error;
+use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::fmt;
 use std::process;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
+#[cfg(feature = "demo")]
 use colors_transform::{Color, Hsl, Rgb};
-use png;
 use qoi::qoi_lib::*;
-use log::{error,info};
+use log::{info, LevelFilter};
+#[cfg(feature = "demo")]
+use log::error;
 
-fn encode_checkerboard() {
+#[cfg(feature = "demo")]
+fn encode_checkerboard() -> Result<(), Box<dyn Error>> {
     let mut pixels: Vec<Pixel> = Vec::with_capacity(64 * 64);
     let red: u8 = 150;
     let green: u8 = 0;
@@ -41,11 +50,13 @@ fn encode_checkerboard() {
         }
     }
 
-    let img: Image = Image::from_pixels(pixels, 64, 64, 4, 0);
-    write_to_file(encode_from_image(img), "checkerboard").expect("Error writing file!");
+    let img: Image = Image::from_pixels(pixels, 64, 64, 4, 0)?;
+    write_to_file(encode_from_image(&img), "checkerboard.qoi")?;
+    Ok(())
 }
 
-fn encode_debug() {
+#[cfg(feature = "demo")]
+fn encode_debug() -> Result<(), Box<dyn Error>> {
     let mut img_data: Vec<u8> = Vec::new();
     //row iterator
     for i in 0..1024 {
@@ -66,152 +77,302 @@ fn encode_debug() {
             }
         }
     }
-    let img: Image = match Image::new(img_data, 1024, 1024, 4, 0) {
-        Ok(image) => image,
-        Err(err) => panic!("Problem generating image: {:?}", err),
-    };
+    let img: Image = Image::new(img_data, 1024, 1024, 4, 0)?;
     let start = SystemTime::now();
-    let img_bytes: Vec<u8> = encode_from_image(img);
-    let stop = match start.elapsed() {
-        Ok(elapsed) => elapsed.as_millis(),
-        Err(e) => {
-            error!("Error: {e:?}");
-            return ();
-        }
-    };
-    info!("Encode took: {} ms.", stop);
-    write_to_file(img_bytes, "test").expect("Error writing file!");
+    let img_bytes: Vec<u8> = encode_from_image(&img);
+    info!("Encode took: {} ms.", start.elapsed()?.as_millis());
+    write_to_file(img_bytes, "test.qoi")?;
+    Ok(())
 }
 
-fn demo() {
+#[cfg(feature = "demo")]
+fn demo() -> Result<(), Box<dyn Error>> {
     let start = SystemTime::now();
-    encode_checkerboard();
-    let stop = match start.elapsed() {
-        Ok(elapsed) => elapsed.as_millis(),
-        Err(e) => {
-            error!("Error: {e:?}");
-            return ();
-        }
-    };
-    println!("Encode took: {} ms.", stop);
-    encode_debug();
+    encode_checkerboard()?;
+    println!("Encode took: {} ms.", start.elapsed()?.as_millis());
+    encode_debug()
 }
 
-//Attempts to encode given png image as second argument into qoi
-fn encode(in_path: &str, out_path: &str) {
+//Exit code for any failure other than a missing input
+const EXIT_FAILURE: i32 = 1;
+//Exit code when the input file or directory does not exist
+const EXIT_INPUT_NOT_FOUND: i32 = 2;
 
-    //Init png decoder, attempt to decode png into bitmap, throw error if unsuccessful
-    let file:File = File::open(in_path).unwrap_or_else(|e| {
-        println!("Error: {:?}", e.to_string());
-        process::exit(1);
-    });
-    let decoder = png::Decoder::new(file);
-    let mut reader = match decoder.read_info() {
-        Ok(reader) => reader,
-        Err(e) => panic!("ERROR: couldn't read file: {e:}"),
-    };
+//The input path given on the command line does not exist
+#[derive(Debug)]
+struct InputNotFound(String);
 
-    //read image metadata
-    let width: u32 = reader.info().width;
-    let height: u32 = reader.info().height;
-    //for now: hardcoded to 4
-    let channels: u8 = 4;
-
-    //create buffer matching the size of png-decoder output, writing size to output
-    let mut buf = vec![0; reader.output_buffer_size()];
-    let info = match reader.next_frame(&mut buf) {
-        Ok(i) => i,
-        Err(e) => panic!("ERROR: {e:?}"),
-    };
+impl fmt::Display for InputNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "input '{}' not found", self.0)
+    }
+}
+
+impl Error for InputNotFound {}
 
-    //convert buffer into vector
-    let bytes = &buf[..info.buffer_size()];
-    let byte_vec: Vec<u8> = bytes.to_vec();
+//Opens the input file at path, reporting a missing file as InputNotFound
+fn open_input_file(path: &str) -> Result<File, Box<dyn Error>> {
+    File::open(path).map_err(|e| -> Box<dyn Error> {
+        match e.kind() {
+            io::ErrorKind::NotFound => Box::new(InputNotFound(path.to_owned())),
+            _ => Box::new(e),
+        }
+    })
+}
 
-    //create bitmap data from raw byte vector
-    let img: Image = match Image::new(byte_vec, height, width, channels, 0) {
-        Ok(image) => image,
-        Err(err) => panic!("Problem generating image: {:?}", err),
+//Opens path for reading, "-" reads from stdin
+fn open_input(path: &str) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(open_input_file(path)?)))
+    }
+}
+
+//Opens path for writing, "-" writes to stdout. An existing file is only overwritten if force is set.
+fn open_output(path: &str, force: bool) -> Result<Box<dyn Write>, Box<dyn Error>> {
+    if path == "-" {
+        return Ok(Box::new(io::stdout().lock()));
+    }
+    let file: File = if force {
+        File::create(path)?
+    } else {
+        File::create_new(path).map_err(|e| -> Box<dyn Error> {
+            match e.kind() {
+                io::ErrorKind::AlreadyExists => format!(
+                    "output '{}' already exists, pass --force to overwrite it",
+                    path
+                )
+                .into(),
+                _ => Box::new(e),
+            }
+        })?
     };
+    Ok(Box::new(BufWriter::new(file)))
+}
 
-    //in case out_path is erroneously passed with suffix
-    let filename = match out_path.strip_suffix(".png") {
-        Some(s) => s,
-        None => out_path
+//Attempts to encode a png or bmp image read from input into qoi, the format is detected from the leading bytes
+fn encode(mut input: Box<dyn Read>, mut output: Box<dyn Write>) -> Result<(), Box<dyn Error>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let img: Image = if bytes.starts_with(b"BM") {
+        decode_bmp(&bytes)?
+    } else {
+        #[cfg(feature = "png")]
+        {
+            decode_png(&bytes)?
+        }
+        #[cfg(not(feature = "png"))]
+        return Err("reading png requires the `png` feature".into());
     };
 
-    write_to_file(encode_from_image(img), filename).expect("ERROR: Can't write file.");
+    encode_to_writer(&img, &mut output)?;
     info!("Encoding successful!");
+    Ok(())
 }
 
+//The file encoding into out_path writes to: a .png or .bmp suffix is replaced by .qoi, which is also appended to
+//every other path not already ending in .qoi, so out.bin becomes out.bin.qoi
+fn qoi_output_path(out_path: &str) -> String {
+    if out_path == "-" {
+        return out_path.to_owned();
+    }
+    let stem: &str = out_path.strip_suffix(".png").or_else(|| out_path.strip_suffix(".bmp")).unwrap_or(out_path);
+    if Path::new(stem).extension() == Some(OsStr::new("qoi")) {
+        stem.to_owned()
+    } else {
+        format!("{}.qoi", stem)
+    }
+}
+
+//Encodes the file at in_path into qoi_output_path(out_path), "-" standing for stdin/stdout
+fn encode_path(in_path: &str, out_path: &str, force: bool) -> Result<(), Box<dyn Error>> {
+    encode(open_input(in_path)?, open_output(&qoi_output_path(out_path), force)?)
+}
 
-fn decode(path: &str) -> Result<Image, std::io::Error> {
-    let f: File = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => panic!("ERROR: {e:?}"),
+//Collects every png or bmp inside dir, descending into subdirectories if recursive.
+//Directories that cannot be read are recorded in failed.
+fn collect_images(dir: &Path, recursive: bool, images: &mut Vec<PathBuf>, failed: &mut Vec<(String, String)>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            failed.push((dir.display().to_string(), e.to_string()));
+            return;
+        }
     };
-    let mut reader = BufReader::new(f);
-    let mut bytes: Vec<u8> = Vec::new();
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                failed.push((dir.display().to_string(), e.to_string()));
+                continue;
+            }
+        };
+        if path.is_dir() {
+            if recursive {
+                collect_images(&path, recursive, images, failed);
+            }
+            continue;
+        }
+        let ext = path.extension().and_then(|e| e.to_str());
+        if ext == Some("png") || ext == Some("bmp") {
+            images.push(path);
+        }
+    }
+}
 
-    reader.read_to_end(&mut bytes)?;
+//Encodes every png or bmp inside dir to a sibling .qoi on `threads` worker threads, 0 meaning one per core.
+//Existing .qoi files are only replaced if force is set. Failures are collected instead of aborting the batch.
+fn encode_dir(
+    dir: &Path,
+    recursive: bool,
+    threads: usize,
+    force: bool,
+    succeeded: &mut usize,
+    failed: &mut Vec<(String, String)>,
+) {
+    let mut images: Vec<PathBuf> = Vec::new();
+    collect_images(dir, recursive, &mut images, failed);
+    let threads: usize = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(images.len().max(1));
 
-    match qoi::qoi_lib::decode(bytes) {
-        Ok(img) => {
-            info!("Decoding successful!");
-            return Ok(img);
-        },
-        Err(err) => panic!("ERROR: {err:?}"),
+    //every worker takes the next file not yet claimed, so slow files do not hold up the others
+    let next: AtomicUsize = AtomicUsize::new(0);
+    let results: Vec<(usize, Vec<(String, String)>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done: usize = 0;
+                    let mut errors: Vec<(String, String)> = Vec::new();
+                    while let Some(path) = images.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let in_path: String = path.display().to_string();
+                        match encode_path(&in_path, &in_path, force) {
+                            Ok(()) => {
+                                info!("Encoded {}", in_path);
+                                done += 1;
+                            },
+                            Err(e) => errors.push((in_path, e.to_string())),
+                        }
+                    }
+                    (done, errors)
+                })
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    });
+    for (done, errors) in results {
+        *succeeded += done;
+        failed.extend(errors);
     }
+    failed.sort();
 }
 
-fn bench(input: &str, output: Option<String>) {
-    
-    let start = SystemTime::now();
-    let out_path = match output {
-        Some(s) => s,
-        None => input.strip_suffix(".png").unwrap_or(input).to_owned()
-    };
+fn decode(mut input: Box<dyn Read>) -> Result<Image, Box<dyn Error>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    input.read_to_end(&mut bytes)?;
 
-    encode(input, &out_path);
+    let img: Image = qoi::qoi_lib::decode(bytes)?;
+    info!("Decoding successful!");
+    Ok(img)
+}
 
-    match start.elapsed() {
-        Ok(elapsed) => {
-            if elapsed.as_millis() == 0 {
-                println!("Encode took {:?} μs to complete", elapsed.as_micros());
-            } else if elapsed.as_millis() > 999 {
-                println!("Encode took {:.3} s to complete", elapsed.as_secs_f32());
-            } else {
-                println!("Encode took {:?} ms to complete", elapsed.as_millis());
-            }
-        },
-        
-        Err(e) => panic!("ERROR: {e:?}"),
+//Output formats supported by the decode command
+const OUTPUT_FORMATS: [&str; 4] = ["png", "ppm", "bmp", "raw"];
+
+//Output format used when none is given, png unless built without the `png` feature
+const DEFAULT_FORMAT: &str = if cfg!(feature = "png") { "png" } else { "bmp" };
+
+//Replaces a .qoi suffix of path by the extension of format, unless path already carries it or is "-"
+fn output_path(path: &str, format: &str) -> String {
+    if path == "-" {
+        return path.to_owned();
     }
-    let start = SystemTime::now();
-    let mut out_path: String = out_path.to_owned();
-    if !out_path.contains(".qoi") {
-        out_path.push_str(".qoi");
+    let mut file_path: String = path.strip_suffix(".qoi").unwrap_or(path).to_owned();
+    if Path::new(&file_path).extension().and_then(|e| e.to_str()) != Some(format) {
+        file_path.push('.');
+        file_path.push_str(format);
     }
-    match decode(&out_path) {
-        Ok(img) => {
-            //Never fails as long as memory does not corrupt thanks to above push_str op.
-            let png_path = out_path.strip_suffix(".qoi").unwrap();
-            img.write_png(&png_path);
-        },
-        Err(e) => panic!("Error: {e:?}")
+    file_path
+}
+
+//Writes img in the given format into the file at file_path, "-" writing to stdout
+fn write_output(
+    img: &Image,
+    format: &str,
+    file_path: &str,
+    force: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut output: Box<dyn Write> = open_output(file_path, force)?;
+    match format {
+        "raw" => img.write_raw(&mut output, 4)?,
+        "ppm" => img.write_ppm_to(&mut output)?,
+        "bmp" => img.write_bmp_to(&mut output)?,
+        #[cfg(feature = "png")]
+        _ => img.write_png_to(&mut output)?,
+        #[cfg(not(feature = "png"))]
+        _ => return Err("writing png requires the `png` feature".into()),
     }
-    match start.elapsed() {
-        Ok(elapsed) => {
-            if elapsed.as_millis() == 0 {
-                println!("Encode took {:?} μs to complete", elapsed.as_micros());
-            } else if elapsed.as_millis() > 999 {
-                println!("Encode took {:.3} s to complete", elapsed.as_secs_f32());
-            } else {
-                println!("Encode took {:?} ms to complete", elapsed.as_millis());
-            }
-        },
-        
-        Err(e) => panic!("ERROR: {e:?}"),
+    output.flush()?;
+    Ok(())
+}
+
+//Prints the header fields of the qoi file at path as "key: value" lines, without decoding any pixels
+fn info(path: &str) -> Result<(), Box<dyn Error>> {
+    let mut file: File = open_input_file(path)?;
+    let file_size: u64 = file.metadata()?.len();
+    let mut head: [u8; 14] = [0; 14];
+    file.read_exact(&mut head).map_err(|_| ImgError::HeaderError)?;
+    let header: QoiHeader = decode_header(&head)?;
+
+    //ratio of the file size to the same image stored as uncompressed rgba
+    let rgba_size: u64 = header.width as u64 * header.height as u64 * 4;
+    println!("width: {}", header.width);
+    println!("height: {}", header.height);
+    println!("channels: {}", header.channels);
+    println!("colorspace: {}", header.colorspace);
+    println!("file_size: {}", file_size);
+    println!("compression_ratio: {:.4}", file_size as f64 / rgba_size.max(1) as f64);
+    Ok(())
+}
+
+//Prints how long `what` took in a unit fitting the duration
+fn print_elapsed(what: &str, elapsed: Duration) {
+    if elapsed.as_millis() == 0 {
+        println!("{} took {:?} μs to complete", what, elapsed.as_micros());
+    } else if elapsed.as_millis() > 999 {
+        println!("{} took {:.3} s to complete", what, elapsed.as_secs_f32());
+    } else {
+        println!("{} took {:?} ms to complete", what, elapsed.as_millis());
+    }
+}
+
+fn bench(input: &str, output: Option<String>, force: bool) -> Result<(), Box<dyn Error>> {
+    let start = SystemTime::now();
+    let qoi_path: String = qoi_output_path(output.as_deref().unwrap_or(input));
+    encode_path(input, &qoi_path, force)?;
+    print_elapsed("Encode", start.elapsed()?);
+
+    let start = SystemTime::now();
+    let img: Image = decode(open_input(&qoi_path)?)?;
+    //the decoded image goes next to the qoi file without replacing the input
+    let stem: &str = qoi_path.strip_suffix(".qoi").unwrap_or(&qoi_path);
+    let decoded_path: String = output_path(&format!("{}.decoded", stem), DEFAULT_FORMAT);
+    write_output(&img, DEFAULT_FORMAT, &decoded_path, force)?;
+    print_elapsed("Decode", start.elapsed()?);
+    Ok(())
+}
+
+//maps the number of --verbose flags to a log level
+fn verbosity_level(count: u8) -> LevelFilter {
+    match count {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
     }
 }
 
@@ -230,13 +391,16 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Encode given [IMAGE] from { png } to qoi. 
+    /// Encode given [IMAGE] from { png, bmp } to qoi, or every such image inside a directory. 
     Encode(EncodeArgs),
     /// Decode given qoi to specified [FORMAT].
     Decode(DecodeArgs),
+    /// Print width, height, channels, colorspace, file size and compression ratio of the qoi [INPUT].
+    Info(InfoArgs),
     /// Benchmark en- and decoder by passing in [IMAGE] and optionally specifying [OUTPUT] file.
     Bench(BenchArgs),
     /// Demo the application.
+    #[cfg(feature = "demo")]
     Demo {
     }
 }
@@ -248,7 +412,17 @@ struct BenchArgs {
     input: String,
     /// Optional output path.
     #[arg(short,long)]
-    output: Option<String>
+    output: Option<String>,
+    /// Overwrite existing output files.
+    #[arg(long)]
+    force: bool
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    /// Qoi file to be inspected
+    #[arg(short,long)]
+    input: String
 }
 
 #[derive(Args)]
@@ -256,12 +430,15 @@ struct DecodeArgs {
     /// Qoi file to be decoded
     #[arg(short,long)]
     input: String,
-    /// Format to transcode into
+    /// Format to transcode into { png, ppm, bmp, raw }, derived from the output file extension if omitted
     #[arg(short,long)]
-    format: String,
+    format: Option<String>,
     /// Optional file path
     #[arg(short,long)]
-    output: Option<String>
+    output: Option<String>,
+    /// Overwrite an existing output file
+    #[arg(long)]
+    force: bool
 }
 
 #[derive(Args)]
@@ -271,43 +448,200 @@ struct EncodeArgs {
     input: String,
     // Optional output path
     #[arg(short,long)]
-    output: Option<String>
+    output: Option<String>,
+    /// Descend into subdirectories when [INPUT] is a directory
+    #[arg(short,long)]
+    recursive: bool,
+    /// Number of files encoded in parallel when [INPUT] is a directory, 0 uses every core
+    #[arg(short,long, default_value_t = 1)]
+    threads: usize,
+    /// Overwrite existing output files
+    #[arg(long)]
+    force: bool
 }
 
-fn main() {
-    let cli: Cli = Cli::parse();
-    
+fn run_decode(args: &DecodeArgs) -> Result<(), Box<dyn Error>> {
+    let out_path = match &args.output {
+        Some(s) => s,
+        None => &args.input
+    };
+    //--format overrides the extension of the output path, DEFAULT_FORMAT otherwise
+    let format: &str = match &args.format {
+        Some(f) => f,
+        None => Path::new(out_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .filter(|e| OUTPUT_FORMATS.contains(e))
+            .unwrap_or(DEFAULT_FORMAT),
+    };
+    if !OUTPUT_FORMATS.contains(&format) {
+        return Err(format!("Unsupported output format '{}', supported formats are: {}", format, OUTPUT_FORMATS.join(", ")).into());
+    }
+    let img: Image = decode(open_input(&args.input)?)?;
+    let file_path: String = output_path(out_path, format);
+    write_output(&img, format, &file_path, args.force)?;
+    info!("Wrote {} to {}", format, file_path);
+    Ok(())
+}
 
-    match &cli.command {
-        Commands::Bench(args) => {
-            bench(&args.input, args.output.clone());
-        },
-        Commands::Decode(args)=> {
-            if args.format != "png" {
-                panic!("Unsupported output format!")
-            } else {
-                let img = match decode(&args.input) {
-                    Ok(i) => i,
-                    Err(e) => panic!("Error: {e:?}")
-                };
-                let out_path = match &args.output {
-                    Some(s) => s,
-                    None => &args.input 
-                };
-                img.write_png(&out_path);
-            }
-        },
-        Commands::Encode(args) => {
-            let out_path = match &args.output {
-                Some(s) => s,
-                None => args.input.strip_suffix(".png").unwrap_or_else(||{
-                    println!("Error: Could not construct output arg from input arg. Please provide explicitly");
-                    process::exit(1);
-                })
-            };
+fn run_encode(args: &EncodeArgs) -> Result<(), Box<dyn Error>> {
+    if Path::new(&args.input).is_dir() {
+        if args.output.is_some() {
+            return Err("--output cannot be used when encoding a directory".into());
+        }
+        let mut succeeded: usize = 0;
+        let mut failed: Vec<(String, String)> = Vec::new();
+        let start = SystemTime::now();
+        encode_dir(
+            Path::new(&args.input),
+            args.recursive,
+            args.threads,
+            args.force,
+            &mut succeeded,
+            &mut failed,
+        );
+        for (path, e) in &failed {
+            println!("Failed: {}: {}", path, e);
+        }
+        println!("Encoded {} files, {} failed.", succeeded, failed.len());
+        print_elapsed("Batch encode", start.elapsed()?);
+        if !failed.is_empty() {
+            return Err(format!("{} files could not be encoded", failed.len()).into());
+        }
+        return Ok(());
+    }
 
-            encode(&args.input, &out_path);
+    let out_path = match &args.output {
+        Some(s) => s,
+        //piped input is piped out again
+        None if args.input == "-" => "-",
+        None if args.input.ends_with(".png") || args.input.ends_with(".bmp") => &args.input,
+        None => {
+            return Err("Could not construct output arg from input arg. Please provide explicitly".into())
         },
+    };
+    encode_path(&args.input, out_path, args.force)
+}
+
+fn run(command: &Commands) -> Result<(), Box<dyn Error>> {
+    match command {
+        Commands::Bench(args) => bench(&args.input, args.output.clone(), args.force),
+        Commands::Decode(args) => run_decode(args),
+        Commands::Encode(args) => run_encode(args),
+        Commands::Info(args) => info(&args.input),
+        #[cfg(feature = "demo")]
         Commands::Demo {  } => demo()
     }
 }
+
+//a missing input is told apart from every other failure
+fn exit_code(e: &(dyn Error + 'static)) -> i32 {
+    if e.is::<InputNotFound>() {
+        EXIT_INPUT_NOT_FOUND
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+fn main() {
+    let cli: Cli = Cli::parse();
+
+    //the library only emits log records, RUST_LOG is respected unless --verbose is given
+    let mut logger = env_logger::Builder::new();
+    logger.filter_level(verbosity_level(0)).parse_default_env();
+    if let Some(count) = cli.verbose.filter(|count| *count > 0) {
+        logger.filter_level(verbosity_level(count));
+    }
+    logger.init();
+
+    if let Err(e) = run(&cli.command) {
+        eprintln!("Error: {}", e);
+        process::exit(exit_code(e.as_ref()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    //Creates an empty scratch directory below the system temp dir
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn exit_code_test() {
+        let missing: Box<dyn Error> = Box::new(InputNotFound("missing.png".to_owned()));
+        assert_eq!(exit_code(missing.as_ref()), EXIT_INPUT_NOT_FOUND);
+        let other: Box<dyn Error> = "something else went wrong".into();
+        assert_eq!(exit_code(other.as_ref()), EXIT_FAILURE);
+
+        let path: PathBuf = std::env::temp_dir().join("qoi_exit_code_test_does_not_exist.png");
+        let err: Box<dyn Error> = open_input_file(&path.display().to_string()).unwrap_err();
+        assert_eq!(exit_code(err.as_ref()), EXIT_INPUT_NOT_FOUND);
+    }
+
+    #[test]
+    fn qoi_output_path_test() {
+        assert_eq!(qoi_output_path("-"), "-");
+        assert_eq!(qoi_output_path("img.png"), "img.qoi");
+        assert_eq!(qoi_output_path("dir/img.bmp"), "dir/img.qoi");
+        assert_eq!(qoi_output_path("img"), "img.qoi");
+        assert_eq!(qoi_output_path("img.qoi"), "img.qoi");
+        assert_eq!(qoi_output_path("out.bin"), "out.bin.qoi");
+    }
+
+    #[test]
+    fn open_output_test() {
+        let dir: PathBuf = scratch_dir("qoi_open_output_test");
+        let path: String = dir.join("out.qoi").display().to_string();
+
+        open_output(&path, false).unwrap().write_all(b"first").unwrap();
+        let err: Box<dyn Error> = match open_output(&path, false) {
+            Ok(_) => panic!("existing output was opened without --force"),
+            Err(e) => e,
+        };
+        assert!(err.to_string().contains("--force"), "{}", err);
+        assert_eq!(exit_code(err.as_ref()), EXIT_FAILURE);
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+
+        open_output(&path, true).unwrap().write_all(b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn encode_dir_test() {
+        let dir: PathBuf = scratch_dir("qoi_encode_dir_test");
+        std::fs::create_dir(dir.join("nested")).unwrap();
+        let names: [&str; 6] = ["a", "b", "c", "d", "e", "nested/f"];
+        for (i, name) in names.iter().enumerate() {
+            let img: Image = Image::solid(3, 2, Pixel::new(i as u8, 0, 0, 255), 4, 0).unwrap();
+            img.write_bmp(dir.join(format!("{}.bmp", name))).unwrap();
+        }
+
+        let (mut succeeded, mut failed): (usize, Vec<(String, String)>) = (0, Vec::new());
+        encode_dir(&dir, false, 3, false, &mut succeeded, &mut failed);
+        assert_eq!((succeeded, failed.len()), (5, 0));
+        assert!(!dir.join("nested/f.qoi").exists());
+
+        succeeded = 0;
+        encode_dir(&dir, true, 0, false, &mut succeeded, &mut failed);
+        assert_eq!((succeeded, failed.len()), (1, 5));
+        assert!(failed.iter().all(|(_, e)| e.contains("--force")));
+
+        let (mut succeeded, mut failed): (usize, Vec<(String, String)>) = (0, Vec::new());
+        encode_dir(&dir, true, 2, true, &mut succeeded, &mut failed);
+        assert_eq!((succeeded, failed.len()), (6, 0));
+        for (i, name) in names.iter().enumerate() {
+            let bytes: Vec<u8> = std::fs::read(dir.join(format!("{}.qoi", name))).unwrap();
+            let expected: Image = Image::solid(3, 2, Pixel::new(i as u8, 0, 0, 255), 4, 0).unwrap();
+            assert!(qoi::qoi_lib::decode(bytes).unwrap() == expected, "{}", name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}